@@ -1,5 +1,12 @@
 -module(logan).
--export([start_link/1, handle/2]).
+-export([start_link/1, handle/2, decode/1]).
+
+%% Message tags of the erl output format, see src/erl.rs
+-define(TAG_WORD,   1).
+-define(TAG_PACKET, 2).
+-define(TAG_ERROR,  3).
+-define(TAG_TIME,   4).
+-define(TAG_STATUS, 5).
 
 
 start_link(Config) ->
@@ -33,10 +40,11 @@ handle(start, State = #{ spawn_port := SpawnPort, dev := Dev, type := Type }) ->
     %% Ask framework to spawn the port process.
     Port =
         SpawnPort(
-          #{ opts => [{line,1024}, binary, use_stdio, exit_status],
+          #{ opts => [{packet,4}, binary, use_stdio, exit_status],
              cmd  => "logan",
              args => [tools:format("~s",[Dev]),
-                      tools:format("~s",[Type])]
+                      tools:format("~s",[Type]),
+                      "format=etf"]
            }),
     maps:put(port, Port, State);
 
@@ -53,12 +61,15 @@ handle(stop, State) ->
 handle(Msg={_,dump}, State) ->
     obj:handle(Msg, State);
 
-%% FIXME: It's probably best to add some kind of protocol, so it can
-%% support multiple packet types.
+%% Decoded messages are passed to the optional sink function.
 handle({Port, Msg}, #{ port := Port }=State) ->
     case Msg of
         {data, Data} ->
-            log:info("data: ~p~n", [Data]),
+            Decoded = decode(Data),
+            case State of
+                #{ sink := Sink } -> Sink(Decoded);
+                _ -> log:info("~p~n", [Decoded])
+            end,
             State;
         {exit_status, Status} ->
             log:info("exit_status: ~p~n", [Status]),
@@ -71,3 +82,11 @@ handle(Msg, State) ->
     State.
 
 
+%% Both the tagged (format=erl) and the external term format
+%% (format=etf) frames decode to the same terms.
+decode(<<131,_/binary>>=Bin)     -> binary_to_term(Bin);
+decode(<<?TAG_WORD, W/binary>>)   -> {word, binary:decode_unsigned(W)};
+decode(<<?TAG_PACKET, P/binary>>) -> {packet, P};
+decode(<<?TAG_ERROR, E/binary>>)  -> {error, E};
+decode(<<?TAG_TIME, T:64>>)       -> {time, T};
+decode(<<?TAG_STATUS, S/binary>>) -> {status, S}.
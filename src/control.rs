@@ -0,0 +1,254 @@
+/* control: Reconfiguration of a running analyzer.
+
+Standard input carries the sample stream, so commands arrive on a
+separate channel: a Unix socket, or an inherited file descriptor.
+Commands are text lines, answered by zero or more reply lines and a
+final "ok" or "error: <reason>" line.
+
+  set <decoder> key=value ..   change options, restarts the decoder
+  enable <decoder>
+  disable <decoder>
+  reset [<decoder>]            return decoder(s) to initial state
+  stats                        sample and item counts
+  help
+
+Commands are handled by the sample loop in between samples, so they
+are answered only while samples keep flowing.
+
+*/
+
+use decoder::{Registry,Options,Item,Decoder,Error};
+use sm::Push;
+use libc;
+use std::fs::{self,File};
+use std::io::{self,BufRead,BufReader,Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixListener;
+use std::sync::mpsc::{channel,Sender,Receiver};
+use std::thread;
+
+pub enum Command {
+    Set(String, Options),
+    Enable(String),
+    Disable(String),
+    Reset(Option<String>),
+    Stats,
+    Help,
+}
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let arg = |n: usize| match words.get(n) {
+        Some(w) => Ok(w.to_string()),
+        None => Err(format!("{}: missing argument", words[0])),
+    };
+    match words.first() {
+        None => Err("empty command".to_string()),
+        Some(&"set") => {
+            let name = arg(1)?;
+            let options = Options::parse(words[2..].iter().map(|w| w.to_string()));
+            Ok(Command::Set(name, options.map_err(|e| e.to_string())?))
+        },
+        Some(&"enable")  => Ok(Command::Enable(arg(1)?)),
+        Some(&"disable") => Ok(Command::Disable(arg(1)?)),
+        Some(&"reset")   => Ok(Command::Reset(arg(1).ok())),
+        Some(&"stats")   => Ok(Command::Stats),
+        Some(&"help")    => Ok(Command::Help),
+        Some(cmd) => Err(format!("unknown command {}", cmd)),
+    }
+}
+
+// A command line from a client, with a channel for the reply.
+pub struct Request {
+    pub line: String,
+    pub reply: Sender<String>,
+}
+
+// Each line is forwarded as a request.  The reply is written back
+// before the next line is read.
+fn serve<R: BufRead, W: Write>(input: R, mut output: W, requests: Sender<Request>) {
+    for line in input.lines() {
+        let line = match line { Ok(line) => line, Err(_) => return };
+        let (reply, rx) = channel();
+        if requests.send(Request { line, reply }).is_err() { return; }
+        let reply = match rx.recv() { Ok(reply) => reply, Err(_) => return };
+        if output.write_all(reply.as_bytes()).and_then(|_| output.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+// Accept clients on a Unix socket.  A stale socket file is removed.
+pub fn listen(path: &str) -> io::Result<Receiver<Request>> {
+    let _ = fs::remove_file(path);
+    let listener = UnixListener::bind(path)?;
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let tx = tx.clone();
+            thread::spawn(move || {
+                if let Ok(input) = stream.try_clone() {
+                    serve(BufReader::new(input), stream, tx);
+                }
+            });
+        }
+    });
+    Ok(rx)
+}
+
+// Commands on an inherited file descriptor, e.g. a socket pair set up
+// by the parent process.  Replies are written to the same descriptor.
+// The descriptor is owned from here on, so stdin, which carries the
+// samples, and stdout and stderr are refused, as is a descriptor that
+// is not open.
+pub fn from_fd(fd: i32) -> io::Result<Receiver<Request>> {
+    if fd <= 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("control_fd {}: stdin, stdout or stderr", fd)));
+    }
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+        let e = io::Error::last_os_error();
+        return Err(io::Error::new(e.kind(), format!("control_fd {}: {}", fd, e)));
+    }
+    let file = unsafe { File::from_raw_fd(fd) };
+    let input = file.try_clone()?;
+    let (tx, rx) = channel();
+    thread::spawn(move || serve(BufReader::new(input), file, tx));
+    Ok(rx)
+}
+
+pub struct Slot {
+    pub name: String,
+    pub enabled: bool,
+    pub items: usize,
+    options: Options,
+    decoder: Box<dyn Decoder>,
+}
+
+impl Slot {
+    // Protocol errors of the decoder, see sm::Errors.
+    pub fn errors(&self) -> Vec<(&'static str, usize)> {
+        self.decoder.errors()
+    }
+}
+
+// A set of named decoders running on the same samples.
+pub struct Session<'a> {
+    registry: &'a Registry,
+    pub slots: Vec<Slot>,
+    pub samples: usize,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(registry: &'a Registry, names: &[String], options: &Options) -> Result<Session<'a>, Error> {
+        let mut slots = vec![];
+        for name in names {
+            slots.push(Slot {
+                name: name.clone(),
+                enabled: true,
+                items: 0,
+                options: options.clone(),
+                decoder: registry.create(name, options)?,
+            });
+        }
+        Ok(Session { registry, slots, samples: 0 })
+    }
+
+    // Push a sample into all enabled decoders.  Output is passed to f
+    // with the slot index and sample index.
+    pub fn push<F>(&mut self, bus: usize, f: &mut F) -> io::Result<()>
+        where F: FnMut(usize, usize, Item) -> io::Result<()>
+    {
+        let t = self.samples;
+        self.samples += 1;
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            if !slot.enabled { continue; }
+            if let Some(item) = slot.decoder.push(bus) {
+                slot.items += 1;
+                f(i, t, item)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn flush<F>(&mut self, f: &mut F) -> io::Result<()>
+        where F: FnMut(usize, usize, Item) -> io::Result<()>
+    {
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            if !slot.enabled { continue; }
+            while let Some(item) = slot.decoder.flush() {
+                slot.items += 1;
+                f(i, self.samples, item)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn slot(&mut self, name: &str) -> Result<&mut Slot, String> {
+        match self.slots.iter_mut().find(|s| s.name == name) {
+            Some(slot) => Ok(slot),
+            None => Err(format!("no decoder {}", name)),
+        }
+    }
+
+    // Execute a command and return the reply lines.
+    pub fn command(&mut self, line: &str) -> String {
+        match self.execute(line) {
+            Ok(reply) => format!("{}ok\n", reply),
+            Err(e) => format!("error: {}\n", e),
+        }
+    }
+
+    fn execute(&mut self, line: &str) -> Result<String, String> {
+        match parse(line)? {
+            Command::Set(name, changes) => {
+                let registry = self.registry;
+                let slot = self.slot(&name)?;
+                let mut options = slot.options.clone();
+                options.extend(&changes);
+                slot.decoder = registry.create(&name, &options).map_err(|e| e.to_string())?;
+                slot.options = options;
+                Ok(String::new())
+            },
+            Command::Enable(name) => {
+                self.slot(&name)?.enabled = true;
+                Ok(String::new())
+            },
+            Command::Disable(name) => {
+                self.slot(&name)?.enabled = false;
+                Ok(String::new())
+            },
+            Command::Reset(Some(name)) => {
+                self.slot(&name)?.decoder.reset();
+                Ok(String::new())
+            },
+            Command::Reset(None) => {
+                for slot in self.slots.iter_mut() { slot.decoder.reset(); }
+                Ok(String::new())
+            },
+            Command::Stats => {
+                let mut rv = format!("samples {}\n", self.samples);
+                for slot in self.slots.iter() {
+                    rv.push_str(&format!("{} {} items {}\n", slot.name,
+                                         if slot.enabled { "enabled" } else { "disabled" },
+                                         slot.items));
+                }
+                Ok(rv)
+            },
+            Command::Help => Ok("\
+set <decoder> key=value ..
+enable <decoder>
+disable <decoder>
+reset [<decoder>]
+stats
+".to_string()),
+        }
+    }
+
+    // Handle pending requests without blocking.
+    pub fn poll(&mut self, requests: &Receiver<Request>) {
+        while let Ok(req) = requests.try_recv() {
+            let reply = self.command(&req.line);
+            let _ = req.reply.send(reply);
+        }
+    }
+}
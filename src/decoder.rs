@@ -0,0 +1,390 @@
+/* decoder: Runtime selection of decoders.
+
+The state machines in sm are generic in input and output type, which
+is good for performance but means each combination needs to be
+written out in code.  This module wraps them behind an object-safe
+trait with a uniform input (bus samples as usize) and output (Item)
+type, and provides a registry that maps decoder names to
+constructors.
+
+Each constructor parses its own options from an Options map, so new
+decoders can be added without changing the binary.  Options that are
+not given on the command line are looked up in the environment as
+LOGAN_<KEY>, e.g. LOGAN_SAMPLERATE.
+
+*/
+
+use sm::{Push,Resync,Errors,uart,slip,syncser,diff};
+use std::collections::HashMap;
+use std::fmt;
+
+// ---- Item ----
+
+// Uniform output type of dynamic decoders.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Item {
+    Byte(u8),          // byte stream, e.g. UART characters
+    Word(usize),       // data word or bus value
+    Packet(Vec<u8>),   // framed data, e.g. SLIP
+}
+
+// Kind of Items a decoder produces, for outputs that need to declare
+// their signals up front.
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Output {
+    Bytes, Words, Packets,
+}
+
+// ---- Decoder ----
+
+// Object-safe decoder trait.  Push is object-safe, so this only fixes
+// the types and adds resync (see sm::Resync) and access to error
+// counts (see sm::Errors), which are otherwise only available
+// statically.  Resync is available for bus samples as usize, the
+// decoder input, and as u8, for captures split by par.
+pub trait Decoder: Push<usize,Item> + Resync<usize> + Resync<u8> + Errors + Send {}
+
+// Adapter from Push machines to Decoder.  Machines without resync
+// points use the default of Resync.
+struct Dynamic<P>(P);
+
+impl<P> Push<usize,Item> for Dynamic<P> where P: Push<usize,Item> {
+    #[inline(always)]
+    fn push(&mut self, input: usize) -> Option<Item> { self.0.push(input) }
+    fn reset(&mut self) { self.0.reset() }
+    fn flush(&mut self) -> Option<Item> { self.0.flush() }
+}
+impl<B,P> Resync<B> for Dynamic<P> where P: Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        self.0.resync(capture, from)
+    }
+}
+impl<P> Errors for Dynamic<P> where P: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { self.0.errors() }
+}
+impl<P> Decoder for Dynamic<P>
+    where P: Push<usize,Item>+Resync<usize>+Resync<u8>+Errors+Send {}
+
+pub fn dynamic<P>(sm: P) -> Box<dyn Decoder>
+    where P: 'static+Push<usize,Item>+Resync<usize>+Resync<u8>+Errors+Send
+{
+    Box::new(Dynamic(sm))
+}
+
+// ---- Errors ----
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownDecoder(String),
+    BadOption(String, String),  // key, message
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownDecoder(name) =>
+                write!(f, "unknown decoder '{}'", name),
+            Error::BadOption(key, msg) =>
+                write!(f, "option '{}': {}", key, msg),
+        }
+    }
+}
+
+// ---- Options ----
+
+// Accepts key=value, --key=value and --key value.
+#[derive(Clone,Debug,Default)]
+pub struct Options {
+    map: HashMap<String,String>,
+}
+impl Options {
+    pub fn new() -> Options { Options::default() }
+    pub fn parse<I>(args: I) -> Result<Options, Error>
+        where I: IntoIterator<Item=String>
+    {
+        let mut o = Options::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, arg) = match arg.strip_prefix("--") {
+                Some(a) => (true, a.to_string()),
+                None    => (false, arg),
+            };
+            match arg.find('=') {
+                Some(i) => o.set(&arg[..i], &arg[i+1..]),
+                None if flag => match args.next() {
+                    Some(v) => o.set(&arg, &v),
+                    None => return Err(Error::BadOption(
+                        arg, "missing value".to_string())),
+                },
+                None => return Err(Error::BadOption(
+                    arg, "expected key=value".to_string())),
+            }
+        }
+        Ok(o)
+    }
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.map.insert(key.to_string(), value.to_string());
+    }
+    // Override with all options set in other.
+    pub fn extend(&mut self, other: &Options) {
+        for (k, v) in other.map.iter() { self.set(k, v); }
+    }
+    pub fn str(&self, key: &str) -> Option<String> {
+        match self.map.get(key) {
+            Some(v) => Some(v.clone()),
+            None => std::env::var(format!("LOGAN_{}", key.to_uppercase())).ok(),
+        }
+    }
+    // Numbers can be decimal or 0x prefixed hexadecimal.
+    pub fn usize(&self, key: &str, default: usize) -> Result<usize, Error> {
+        match self.str(key) {
+            None => Ok(default),
+            Some(v) => {
+                let rv = match v.strip_prefix("0x") {
+                    Some(hex) => usize::from_str_radix(hex, 16),
+                    None => v.parse::<usize>(),
+                };
+                rv.map_err(|e| Error::BadOption(key.to_string(), e.to_string()))
+            }
+        }
+    }
+    // Channel number, i.e. a bit of the bus sample.
+    pub fn channel(&self, key: &str, default: usize) -> Result<usize, Error> {
+        let channel = self.usize(key, default)?;
+        if channel >= usize::BITS as usize {
+            return Err(Error::BadOption(key.to_string(),
+                                        format!("channel {} out of range", channel)));
+        }
+        Ok(channel)
+    }
+    // Comma-separated list of numbers.
+    pub fn list(&self, key: &str) -> Result<Option<Vec<usize>>, Error> {
+        match self.str(key) {
+            None => Ok(None),
+            Some(v) => {
+                let mut o = Options::new();
+                let mut rv = vec![];
+                for e in v.split(',') {
+                    o.set(key, e.trim());
+                    rv.push(o.usize(key, 0)?);
+                }
+                Ok(Some(rv))
+            }
+        }
+    }
+    pub fn bool(&self, key: &str, default: bool) -> Result<bool, Error> {
+        match self.str(key) {
+            None => Ok(default),
+            Some(v) => match &v[..] {
+                "1" | "true"  | "yes" | "on"  => Ok(true),
+                "0" | "false" | "no"  | "off" => Ok(false),
+                _ => Err(Error::BadOption(key.to_string(),
+                                          format!("not a boolean: {}", v))),
+            }
+        }
+    }
+}
+
+// ---- Registry ----
+
+pub type Constructor = fn(&Options) -> Result<Box<dyn Decoder>, Error>;
+
+pub struct Entry {
+    pub name:        &'static str,
+    pub description: &'static str,
+    pub options:     &'static [(&'static str, &'static str)],  // key, help
+    pub output:      Output,
+    pub new:         Constructor,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+impl Registry {
+    pub fn new() -> Registry { Registry::default() }
+    // Registry containing the decoders defined below.
+    pub fn builtin() -> Registry {
+        let mut r = Registry::new();
+        r.add(Entry {
+            name: "uart",
+            description: "UART, output bytes",
+            options: UART_OPTIONS,
+            output: Output::Bytes,
+            new: new_uart,
+        });
+        r.add(Entry {
+            name: "slip",
+            description: "SLIP packets on UART",
+            options: SLIP_OPTIONS,
+            output: Output::Packets,
+            new: new_slip,
+        });
+        r.add(Entry {
+            name: "spi",
+            description: "SPI / synchronous serial, output words",
+            options: SPI_OPTIONS,
+            output: Output::Words,
+            new: new_spi,
+        });
+        r.add(Entry {
+            name: "ice40",
+            description: "iCE40 FPGA SPI boot (spi with fixed wiring)",
+            options: SPI_OPTIONS,
+            output: Output::Words,
+            new: new_ice40,
+        });
+        r.add(Entry {
+            name: "diff",
+            description: "Bus value on every change",
+            options: &[],
+            output: Output::Words,
+            new: new_diff,
+        });
+        r
+    }
+    // Entries added later override entries with the same name.
+    pub fn add(&mut self, entry: Entry) {
+        self.entries.retain(|e| e.name != entry.name);
+        self.entries.push(entry);
+    }
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+    pub fn create(&self, name: &str, options: &Options)
+                  -> Result<Box<dyn Decoder>, Error> {
+        match self.get(name) {
+            Some(entry) => (entry.new)(options),
+            None => Err(Error::UnknownDecoder(name.to_string())),
+        }
+    }
+}
+
+// ---- Built-in decoders ----
+
+const UART_OPTIONS: &[(&str, &str)] = &[
+    ("samplerate", "sample rate in Hz (2000000)"),
+    ("baudrate",   "baud rate (115200)"),
+    ("channel",    "input channel (0)"),
+    ("bits",       "data bits (8)"),
+];
+const SLIP_OPTIONS: &[(&str, &str)] = &[
+    ("samplerate", "sample rate in Hz (2000000)"),
+    ("baudrate",   "baud rate (115200)"),
+    ("channel",    "input channel (0)"),
+    ("end",        "frame end character (0x0D)"),
+    ("esc",        "escape character (0x0C)"),
+    ("esc_end",    "escaped end character (0x0B)"),
+    ("esc_esc",    "escaped escape character (0x0A)"),
+];
+const SPI_OPTIONS: &[(&str, &str)] = &[
+    ("clock",    "clock channel"),
+    ("data",     "data channel"),
+    ("frame",    "frame (chip select) channel"),
+    ("framed",   "use frame channel (boolean)"),
+    ("active",   "frame active level"),
+    ("edge",     "sampling edge: 0 falling, 1 rising"),
+    ("polarity", "clock idle level"),
+    ("timeout",  "frame timeout in samples, 0 disables"),
+    ("bits",     "word size"),
+];
+
+pub fn uart_config(o: &Options) -> Result<uart::Config, Error> {
+    let samplerate = o.usize("samplerate", 2000000)?;
+    let baudrate   = o.usize("baudrate", 115200)?;
+    if baudrate == 0 || baudrate > samplerate {
+        return Err(Error::BadOption(
+            "baudrate".to_string(),
+            "needs to be nonzero and not above samplerate".to_string()));
+    }
+    Ok(uart::Config {
+        period:  samplerate / baudrate,
+        nb_bits: o.usize("bits", 8)?,
+        channel: o.channel("channel", 0)?,
+    })
+}
+
+fn new_uart(o: &Options) -> Result<Box<dyn Decoder>, Error> {
+    let config = uart_config(o)?;
+    if config.nb_bits <= 8 {
+        Ok(dynamic(uart::init(config).map(|w| Item::Byte(w as u8))))
+    }
+    else {
+        Ok(dynamic(uart::init(config).map(Item::Word)))
+    }
+}
+
+pub fn slip_config(o: &Options) -> Result<slip::Config, Error> {
+    Ok(slip::Config {
+        end:     o.usize("end",     0x0D)? as u8,
+        esc:     o.usize("esc",     0x0C)? as u8,
+        esc_end: o.usize("esc_end", 0x0B)? as u8,
+        esc_esc: o.usize("esc_esc", 0x0A)? as u8,
+    })
+}
+
+fn new_slip(o: &Options) -> Result<Box<dyn Decoder>, Error> {
+    let uart = uart::init(uart_config(o)?);
+    let slip = slip::init(slip_config(o)?);
+    Ok(dynamic(uart.then(slip).map(Item::Packet)))
+}
+
+pub fn spi_config(o: &Options, d: syncser::Config) -> Result<syncser::Config, Error> {
+    let frame_timeout = o.usize("timeout", d.frame_timeout)?;
+    Ok(syncser::Config {
+        clock_channel:  o.channel("clock",    d.clock_channel)?,
+        data_channel:   o.channel("data",     d.data_channel)?,
+        frame_channel:  o.channel("frame",    d.frame_channel)?,
+        frame_enable:   o.bool("framed",    d.frame_enable)?,
+        frame_active:   o.usize("active",   d.frame_active)?,
+        clock_edge:     o.usize("edge",     d.clock_edge)?,
+        clock_polarity: o.usize("polarity", d.clock_polarity)?,
+        nb_bits:        o.usize("bits",     d.nb_bits)?,
+        timeout_enable: frame_timeout > 0,
+        frame_timeout,
+    })
+}
+
+fn new_spi(o: &Options) -> Result<Box<dyn Decoder>, Error> {
+    let config = spi_config(o, syncser::config())?;
+    Ok(dynamic(syncser::init(config).map(Item::Word)))
+}
+
+/* Illustrating SPI for a slightly more involved example: booting a
+   iCE40 FPGA.  This involves multiple signals.
+
+   GND                      (black)
+
+   BBB SPI0 test
+   0
+   1
+   2 CDONE  gpio3_14 P9_31
+   3 CRESET gpio3_15 P9_29  (green)
+   4 MOSI (D1)       P9_18  (blue)
+   5 MISO (D0)       P9_21  (purple)
+   6 SCLK            P9_22  (grey)
+   7 CS     gpio1_12 P8_12  (white)
+*/
+
+fn new_ice40(o: &Options) -> Result<Box<dyn Decoder>, Error> {
+    let defaults = syncser::Config {
+        clock_channel:   5,
+        data_channel:    4,
+        frame_channel:   0,
+        clock_edge:      0,
+        clock_polarity:  0,
+        frame_enable:    true,
+        frame_active:    0,
+        frame_timeout:   0, //disabled
+        timeout_enable:  false,
+        nb_bits:         8
+    };
+    let config = spi_config(o, defaults)?;
+    Ok(dynamic(syncser::init(config).map(Item::Word)))
+}
+
+fn new_diff(_o: &Options) -> Result<Box<dyn Decoder>, Error> {
+    Ok(dynamic(diff::init().map(Item::Word)))
+}
@@ -0,0 +1,99 @@
+/* erl: Message framing for Erlang ports.
+
+Messages are framed with a 4 byte big endian length prefix, so the
+port can be opened with {packet,4}.  The payload is either:
+
+- tagged: a tag byte followed by the message body, or
+- etf: an Erlang external term, decoded with binary_to_term/1.
+
+  tag         body                       term
+  1 word      big endian unsigned        {word, N}
+  2 packet    packet bytes               {packet, <<..>>}
+  3 error     text                       {error, <<..>>}
+  4 time      u64 sample index           {time, T}
+  5 status    text                       {status, <<..>>}
+
+Tag values are distinct from the external term format version byte
+131, so a receiver can accept both.  See erl/logan.erl
+
+*/
+
+use std::io::{self,Write};
+
+pub const TAG_WORD:   u8 = 1;
+pub const TAG_PACKET: u8 = 2;
+pub const TAG_ERROR:  u8 = 3;
+pub const TAG_TIME:   u8 = 4;
+pub const TAG_STATUS: u8 = 5;
+
+pub enum Msg<'a> {
+    Word(usize, usize),  // value, number of bytes in tagged form
+    Packet(&'a [u8]),
+    Error(&'a str),
+    Time(usize),
+    Status(&'a str),
+}
+
+pub fn tagged(msg: &Msg) -> Vec<u8> {
+    let mut rv = vec![];
+    match *msg {
+        Msg::Word(w, n) => {
+            rv.push(TAG_WORD);
+            let n = n.clamp(1, 8);
+            rv.extend_from_slice(&(w as u64).to_be_bytes()[8-n..]);
+        },
+        Msg::Packet(p) => { rv.push(TAG_PACKET); rv.extend_from_slice(p); },
+        Msg::Error(e)  => { rv.push(TAG_ERROR);  rv.extend_from_slice(e.as_bytes()); },
+        Msg::Time(t)   => { rv.push(TAG_TIME);   rv.extend_from_slice(&(t as u64).to_be_bytes()); },
+        Msg::Status(s) => { rv.push(TAG_STATUS); rv.extend_from_slice(s.as_bytes()); },
+    }
+    rv
+}
+
+// External term format encoding of the subset used here.
+fn etf_atom(out: &mut Vec<u8>, atom: &str) {
+    out.push(119);  // SMALL_ATOM_UTF8_EXT
+    out.push(atom.len() as u8);
+    out.extend_from_slice(atom.as_bytes());
+}
+fn etf_int(out: &mut Vec<u8>, n: usize) {
+    if n < 256 {
+        out.push(97);  // SMALL_INTEGER_EXT
+        out.push(n as u8);
+    }
+    else if n <= i32::MAX as usize {
+        out.push(98);  // INTEGER_EXT
+        out.extend_from_slice(&(n as i32).to_be_bytes());
+    }
+    else {
+        let digits: Vec<u8> = (n as u64).to_le_bytes().iter().cloned()
+            .rev().skip_while(|&d| d == 0).collect();
+        out.push(110);  // SMALL_BIG_EXT
+        out.push(digits.len() as u8);
+        out.push(0);    // positive
+        out.extend(digits.iter().rev());
+    }
+}
+fn etf_binary(out: &mut Vec<u8>, bin: &[u8]) {
+    out.push(109);  // BINARY_EXT
+    out.extend_from_slice(&(bin.len() as u32).to_be_bytes());
+    out.extend_from_slice(bin);
+}
+
+pub fn etf(msg: &Msg) -> Vec<u8> {
+    let mut rv = vec![131, 104, 2];  // version, SMALL_TUPLE_EXT arity 2
+    match *msg {
+        Msg::Word(w, _) => { etf_atom(&mut rv, "word");   etf_int(&mut rv, w); },
+        Msg::Packet(p)  => { etf_atom(&mut rv, "packet"); etf_binary(&mut rv, p); },
+        Msg::Error(e)   => { etf_atom(&mut rv, "error");  etf_binary(&mut rv, e.as_bytes()); },
+        Msg::Time(t)    => { etf_atom(&mut rv, "time");   etf_int(&mut rv, t); },
+        Msg::Status(s)  => { etf_atom(&mut rv, "status"); etf_binary(&mut rv, s.as_bytes()); },
+    }
+    rv
+}
+
+// {packet,4} framing.
+pub fn frame<W: Write>(out: &mut W, payload: &[u8]) -> io::Result<()> {
+    out.write_all(&(payload.len() as u32).to_be_bytes())?;
+    out.write_all(payload)
+}
@@ -0,0 +1,420 @@
+/* gen: Bit stream generators.
+
+The inverse of the decoders: words are encoded into oversampled bus
+samples, e.g. to produce test sequences or capture files.  For SPI
+it is hard enough to write a test sequence by hand, see doc/lars.txt
+"Bit generators".
+
+Generators take the decoder configuration where there is one, so a
+generated stream decodes with the same settings.  Each signal is
+placed on its configured channel, other channels are 0.
+
+To test how decoders cope with real signals, a generated stream can
+be impaired, see Faults: a sample clock that is off or drifting,
+jitter, glitches, inverted bits, and a capture that starts in the
+middle of a frame.  Protocol level faults are part of the generator:
+missing UART stop bits (stop_bits and idle 0) and SPI frames that end
+early (truncate).
+
+*/
+
+use sm::{uart,syncser,slip};
+use decoder::{self,Options,Error};
+
+pub trait Encode {
+    // Bus samples for a sequence of words.
+    fn encode(&self, words: &[usize]) -> Vec<usize>;
+    // Mask of the channels that carry signals.
+    fn channels(&self) -> usize;
+}
+
+// Repeat each sample n times.
+pub fn oversample(samples: &[usize], n: usize) -> Vec<usize> {
+    samples.iter().flat_map(|&s| (0..n).map(move |_| s)).collect()
+}
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Parity {
+    None, Even, Odd,
+}
+
+impl Parity {
+    pub fn parse(name: &str) -> Option<Parity> {
+        match name {
+            "none" => Some(Parity::None),
+            "even" => Some(Parity::Even),
+            "odd"  => Some(Parity::Odd),
+            _ => None,
+        }
+    }
+}
+
+// ---- UART ----
+
+#[derive(Copy,Clone)]
+pub struct Uart {
+    pub config: uart::Config,
+    pub parity: Parity,
+    pub stop_bits: usize,
+    pub idle: usize,  // idle bits before each frame
+}
+
+pub fn uart(config: uart::Config) -> Uart {
+    Uart { config, parity: Parity::None, stop_bits: 1, idle: 1 }
+}
+
+impl Uart {
+    // Line levels of a frame, one per bit, LSB first.
+    pub fn bits(&self, word: usize) -> Vec<usize> {
+        let c = &self.config;
+        let mut bits = vec![1; self.idle];
+        bits.push(0);
+        for i in 0..c.nb_bits { bits.push((word >> i) & 1); }
+        let ones = (word & ((1 << c.nb_bits) - 1)).count_ones() as usize;
+        match self.parity {
+            Parity::None => (),
+            Parity::Even => bits.push(ones & 1),
+            Parity::Odd  => bits.push((ones & 1) ^ 1),
+        }
+        bits.extend((0..self.stop_bits).map(|_| 1));
+        bits
+    }
+}
+
+impl Encode for Uart {
+    fn encode(&self, words: &[usize]) -> Vec<usize> {
+        let bits: Vec<usize> = words.iter()
+            .flat_map(|&w| self.bits(w))
+            .map(|bit| bit << self.config.channel)
+            .collect();
+        oversample(&bits, self.config.period)
+    }
+    fn channels(&self) -> usize {
+        1 << self.config.channel
+    }
+}
+
+// ---- SPI ----
+
+/* Each bit takes two half periods, with the clock changing to the
+sampling edge level in the middle, so data is stable around the
+sampling edge in all 4 modes.  Words are shifted out MSB first.  With
+frame_enable, each word is framed by the chip select, followed by one
+period with the frame inactive.  With truncate, the last word misses
+that many of its last bits. */
+#[derive(Copy,Clone)]
+pub struct Spi {
+    pub config: syncser::Config,
+    pub period: usize,    // samples per half clock period
+    pub truncate: usize,  // bits missing from the last word
+}
+
+pub fn spi(config: syncser::Config, period: usize) -> Spi {
+    Spi { config, period, truncate: 0 }
+}
+
+impl Encode for Spi {
+    fn encode(&self, words: &[usize]) -> Vec<usize> {
+        let c = &self.config;
+        let frame = |active: bool| {
+            if !c.frame_enable { 0 }
+            else if active { c.frame_active << c.frame_channel }
+            else { (c.frame_active ^ 1) << c.frame_channel }
+        };
+        let idle = frame(false) | (c.clock_polarity << c.clock_channel);
+        let mut bus = vec![idle];
+        for (n, &word) in words.iter().enumerate() {
+            let missing = if n + 1 == words.len() { self.truncate.min(c.nb_bits) } else { 0 };
+            for shift in (missing..c.nb_bits).rev() {
+                let data = ((word >> shift) & 1) << c.data_channel;
+                bus.push(frame(true) | ((c.clock_edge ^ 1) << c.clock_channel) | data);
+                bus.push(frame(true) | (c.clock_edge << c.clock_channel) | data);
+            }
+            if c.frame_enable { bus.push(idle); }
+        }
+        // End with the clock at the idle level.
+        bus.push(idle);
+        oversample(&bus, self.period)
+    }
+    fn channels(&self) -> usize {
+        let c = &self.config;
+        let frame = if c.frame_enable { 1 << c.frame_channel } else { 0 };
+        (1 << c.clock_channel) | (1 << c.data_channel) | frame
+    }
+}
+
+// ---- I2C ----
+
+/* A single transaction: start condition, the words as bytes MSB first
+each followed by an ACK from the receiver, and a stop condition.  The
+first word is the address byte, see address().  The data line changes
+one sample after the clock falls, so the period is at least 2. */
+#[derive(Copy,Clone)]
+pub struct I2c {
+    pub scl: usize,     // clock channel
+    pub sda: usize,     // data channel
+    pub period: usize,  // samples per half clock period
+}
+
+pub fn i2c(scl: usize, sda: usize, period: usize) -> I2c {
+    I2c { scl, sda, period }
+}
+
+// Address byte for a 7 bit address.
+pub fn address(addr: usize, read: bool) -> usize {
+    (addr << 1) | (read as usize)
+}
+
+impl I2c {
+    fn level(&self, scl: usize, sda: usize) -> usize {
+        (scl << self.scl) | (sda << self.sda)
+    }
+    fn bit(&self, bus: &mut Vec<usize>, sda: usize) {
+        let p = self.period.max(2);
+        let last = bus.last().map(|&b| (b >> self.sda) & 1).unwrap_or(1);
+        bus.push(self.level(0, last));
+        bus.extend((1..p).map(|_| self.level(0, sda)));
+        bus.extend((0..p).map(|_| self.level(1, sda)));
+    }
+}
+
+impl Encode for I2c {
+    fn encode(&self, words: &[usize]) -> Vec<usize> {
+        let p = self.period.max(2);
+        let mut bus = vec![];
+        // Idle, start.
+        bus.extend((0..p).map(|_| self.level(1, 1)));
+        bus.extend((0..p).map(|_| self.level(1, 0)));
+        for &word in words {
+            for shift in (0..8).rev() { self.bit(&mut bus, (word >> shift) & 1); }
+            self.bit(&mut bus, 0);
+        }
+        // Stop, idle.
+        self.bit(&mut bus, 0);
+        bus.extend((0..p).map(|_| self.level(1, 1)));
+        bus
+    }
+    fn channels(&self) -> usize {
+        self.level(1, 1)
+    }
+}
+
+// ---- SLIP ----
+
+// Packet with escapes, terminated by the end character.
+pub fn slip_frame(c: &slip::Config, packet: &[u8]) -> Vec<u8> {
+    let mut rv = vec![];
+    for &b in packet {
+        if b == c.end { rv.push(c.esc); rv.push(c.esc_end); }
+        else if b == c.esc { rv.push(c.esc); rv.push(c.esc_esc); }
+        else { rv.push(b); }
+    }
+    rv.push(c.end);
+    rv
+}
+
+// SLIP on UART.  Each encode call is a single packet.
+#[derive(Copy,Clone)]
+pub struct Slip {
+    pub config: slip::Config,
+    pub uart: Uart,
+}
+
+pub fn slip(config: slip::Config, uart: Uart) -> Slip {
+    Slip { config, uart }
+}
+
+impl Slip {
+    pub fn packets(&self, packets: &[Vec<u8>]) -> Vec<usize> {
+        packets.iter().flat_map(|p| self.encode(&p.iter().map(|&b| b as usize).collect::<Vec<_>>()))
+            .collect()
+    }
+}
+
+impl Encode for Slip {
+    fn encode(&self, words: &[usize]) -> Vec<usize> {
+        let packet: Vec<u8> = words.iter().map(|&w| w as u8).collect();
+        let bytes: Vec<usize> = slip_frame(&self.config, &packet).iter().map(|&b| b as usize).collect();
+        self.uart.encode(&bytes)
+    }
+    fn channels(&self) -> usize {
+        self.uart.channels()
+    }
+}
+
+// ---- Faults ----
+
+// Xorshift generator, so an impaired stream is reproducible from its
+// seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    fn chance(&mut self, p: f64) -> bool {
+        p > 0.0 && ((self.next() >> 11) as f64) < p * (1u64 << 53) as f64
+    }
+}
+
+/* Impairments, applied in this order: the stream is resampled at rate
+samples per generated sample, with rate changing by drift every
+sample.  A rate of 1.02 is a transmitter that is 2% fast.  Then each
+edge on the channels in mask moves by up to jitter samples either
+way, without passing the previous edge.  Every sample has a chance of
+a 1 sample glitch, and a chance of an inversion of width samples,
+e.g. the bit period for bit errors, on a random channel in mask.
+Finally skip samples are dropped, so the capture starts mid-frame. */
+#[derive(Copy,Clone,Debug)]
+pub struct Faults {
+    pub seed: u64,
+    pub rate: f64,
+    pub drift: f64,
+    pub jitter: usize,
+    pub glitch: f64,   // probability per sample
+    pub flip: f64,     // probability per sample
+    pub width: usize,  // samples per flip
+    pub skip: usize,
+    pub mask: usize,   // channels, usually Encode::channels()
+}
+
+// No faults.
+pub fn faults(mask: usize) -> Faults {
+    Faults {
+        seed: 1, rate: 1.0, drift: 0.0, jitter: 0,
+        glitch: 0.0, flip: 0.0, width: 1, skip: 0, mask,
+    }
+}
+
+impl Faults {
+    pub fn apply(&self, samples: &[usize]) -> Vec<usize> {
+        let mut rng = Rng::new(self.seed);
+        let mut out = self.resample(samples);
+        let channels: Vec<usize> = (0..usize::BITS as usize)
+            .filter(|&c| (self.mask >> c) & 1 == 1)
+            .collect();
+        if self.jitter > 0 && !out.is_empty() {
+            for &c in channels.iter() { self.move_edges(&mut rng, &mut out, c); }
+        }
+        if !channels.is_empty() {
+            for t in 0..out.len() {
+                if rng.chance(self.glitch) {
+                    out[t] ^= 1 << channels[rng.below(channels.len())];
+                }
+                if rng.chance(self.flip) {
+                    let bit = 1 << channels[rng.below(channels.len())];
+                    let end = (t + self.width).min(out.len());
+                    for x in out[t..end].iter_mut() { *x ^= bit; }
+                }
+            }
+        }
+        out.drain(..self.skip.min(out.len()));
+        out
+    }
+
+    fn resample(&self, samples: &[usize]) -> Vec<usize> {
+        if self.rate == 1.0 && self.drift == 0.0 { return samples.to_vec(); }
+        let mut out = vec![];
+        let (mut pos, mut rate) = (0.0, self.rate);
+        while rate > 0.0 && (pos as usize) < samples.len() {
+            out.push(samples[pos as usize]);
+            pos += rate;
+            rate += self.drift;
+        }
+        out
+    }
+
+    fn move_edges(&self, rng: &mut Rng, out: &mut [usize], c: usize) {
+        let bit = 1 << c;
+        let orig = out.to_vec();
+        let (mut level, mut from) = (orig[0] & bit, 0);
+        let fill = |out: &mut [usize], level: usize| {
+            for x in out.iter_mut() { *x = (*x & !bit) | level; }
+        };
+        for t in 1..orig.len() {
+            if (orig[t] ^ orig[t-1]) & bit == 0 { continue; }
+            let to = (t + rng.below(2 * self.jitter + 1)).saturating_sub(self.jitter)
+                .max(from + 1).min(orig.len());
+            fill(&mut out[from..to], level);
+            level ^= bit;
+            from = to;
+        }
+        fill(&mut out[from..], level);
+    }
+}
+
+// ---- Options ----
+
+// Generator for a protocol, configured with the same options as the
+// corresponding decoder.  SPI and I2C clocks run at baudrate.
+pub fn from_options(name: &str, o: &Options) -> Result<Box<dyn Encode>, Error> {
+    let uart_gen = |o: &Options| -> Result<Uart, Error> {
+        let mut g = uart(decoder::uart_config(o)?);
+        g.stop_bits = o.usize("stop", 1)?;
+        g.idle = o.usize("idle", 1)?;
+        if let Some(parity) = o.str("parity") {
+            g.parity = match Parity::parse(&parity) {
+                Some(parity) => parity,
+                None => return Err(Error::BadOption("parity".to_string(), parity)),
+            };
+        }
+        Ok(g)
+    };
+    let half_period = |o: &Options| -> Result<usize, Error> {
+        let samplerate = o.usize("samplerate", 2000000)?;
+        let baudrate = o.usize("baudrate", 100000)?;
+        if baudrate == 0 || 2 * baudrate > samplerate {
+            return Err(Error::BadOption(
+                "baudrate".to_string(),
+                "needs to be nonzero and not above samplerate/2".to_string()));
+        }
+        Ok(samplerate / baudrate / 2)
+    };
+    match name {
+        "uart" => Ok(Box::new(uart_gen(o)?)),
+        "slip" => Ok(Box::new(slip(decoder::slip_config(o)?, uart_gen(o)?))),
+        "spi"  => {
+            let mut g = spi(decoder::spi_config(o, syncser::config())?, half_period(o)?);
+            g.truncate = o.usize("truncate", 0)?;
+            Ok(Box::new(g))
+        },
+        "i2c"  => Ok(Box::new(i2c(o.channel("scl", 0)?, o.channel("sda", 1)?, half_period(o)?))),
+        _ => Err(Error::UnknownDecoder(name.to_string())),
+    }
+}
+
+fn float(o: &Options, key: &str, default: f64) -> Result<f64, Error> {
+    match o.str(key) {
+        None => Ok(default),
+        Some(v) => v.parse::<f64>().map_err(|e| Error::BadOption(key.to_string(), e.to_string())),
+    }
+}
+
+// Faults on the given channels.  The rate is given as an error,
+// e.g. rate_error=0.02 for a rate of 1.02.
+pub fn faults_from_options(o: &Options, mask: usize) -> Result<Faults, Error> {
+    let rate = 1.0 + float(o, "rate_error", 0.0)?;
+    if rate <= 0.0 {
+        return Err(Error::BadOption("rate_error".to_string(), "needs to be above -1".to_string()));
+    }
+    Ok(Faults {
+        seed:   o.usize("seed", 1)? as u64,
+        rate,
+        drift:  float(o, "drift", 0.0)?,
+        jitter: o.usize("jitter", 0)?,
+        glitch: float(o, "glitch", 0.0)?,
+        flip:   float(o, "flip", 0.0)?,
+        width:  o.usize("flip_width", 1)?,
+        skip:   o.usize("skip", 0)?,
+        mask,
+    })
+}
@@ -1,24 +1,33 @@
 use std::io::{self, Read, Write};
+use std::fs;
+use std::sync::{Arc,Mutex,Weak};
+use std::time::{Duration,Instant};
+use std::thread;
+use memmap2::Mmap;
 
 /* Manually buffered standard input.  Buffer size such that write from
-Saleae driver doesn't need to be chunked. */
+Saleae driver doesn't need to be chunked.  Iteration ends at end of
+file. */
 pub struct Buf8 {
     buf: [u8; 262144],
-    offset: usize
+    offset: usize,
+    len: usize,
 }
 impl Iterator for Buf8 {
     type Item = u8;
     #[inline(always)]
     fn next(&mut self) -> Option<u8> {
         loop {
-            if self.offset < self.buf.len() {
+            if self.offset < self.len {
                 let rv = self.buf[self.offset];
                 self.offset += 1;
                 return Some(rv);
             }
-            match io::stdin().read_exact(&mut self.buf) {
+            match io::stdin().read(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(n) => self.len = n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
                 Err(err) => panic!("{}",err),
-                Ok(()) => ()
             }
             self.offset = 0;
         }
@@ -26,22 +35,149 @@ impl Iterator for Buf8 {
 }
 pub fn stdin8() -> Buf8 {
     Buf8 {
-        buf: [0; 262144], 
-        offset: 262144
+        buf: [0; 262144],
+        offset: 0,
+        len: 0,
     }
 }
 
-#[inline]
-pub fn write_byte(b: u8) {
-    let bs = [b];
-    match io::stdout().write(&bs) {
-        Err(err) => panic!("{}",err),
-        Ok(_) => (),
+/* Map a raw capture file into memory, e.g. for par.  The file should
+not be modified while it is mapped. */
+pub fn load(path: &str) -> io::Result<Mmap> {
+    let file = fs::File::open(path)?;
+    unsafe { Mmap::map(&file) }
+}
+
+/* Buffered output sink.  Write::flush marks the end of an item, e.g.
+as called by output::Writer, and the flush policy decides whether the
+buffer is actually written out.  Remaining data is written when the
+sink is dropped. */
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Flush {
+    Item,            // at the end of every item
+    Line,            // at the end of an item that contains a newline
+    Bytes(usize),    // when at least this many bytes are buffered
+    Idle(Duration),  // when no output was produced for this long
+}
+
+// Buffer limit for the Line and Idle policies.
+const SINK_MAX: usize = 65536;
+
+impl Flush {
+    // item, line, bytes:<n> or idle:<ms>
+    pub fn parse(spec: &str) -> Option<Flush> {
+        let mut parts = spec.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("item"), None) => Some(Flush::Item),
+            (Some("line"), None) => Some(Flush::Line),
+            (Some("bytes"), Some(n)) => n.parse().ok().map(Flush::Bytes),
+            (Some("idle"), Some(ms)) => ms.parse().ok().map(|ms| Flush::Idle(Duration::from_millis(ms))),
+            _ => None,
+        }
+    }
+}
+
+struct SinkState<W: Write> {
+    out: W,
+    buf: Vec<u8>,
+    newline: bool,   // newline written since last flush
+    last: Instant,   // last write
+    error: Option<io::Error>,  // from the idle flusher, for the next call
+}
+impl<W: Write> SinkState<W> {
+    fn flush_buf(&mut self) -> io::Result<()> {
+        self.newline = false;
+        if self.buf.is_empty() { return Ok(()); }
+        let rv = self.out.write_all(&self.buf).and_then(|_| self.out.flush());
+        self.buf.clear();
+        rv
     }
-    match io::stdout().flush() {
-        Err(err) => panic!("{}",err),
-        Ok(_) => ()
+    fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
 
+pub struct Sink<W: Write> {
+    state: Arc<Mutex<SinkState<W>>>,
+    policy: Flush,
+}
+
+impl<W: Write> Sink<W> {
+    /* Without a timer, an Idle sink writes its buffer on the first
+       write after the timeout, or when full.  Use idle() to have it
+       written while there is no output. */
+    pub fn new(out: W, policy: Flush) -> Sink<W> {
+        let state = Arc::new(Mutex::new(SinkState {
+            out,
+            buf: vec![],
+            newline: false,
+            last: Instant::now(),
+            error: None,
+        }));
+        Sink { state, policy }
+    }
+}
 
+impl<W: Write+Send+'static> Sink<W> {
+    // The idle timeout needs a timer, as the sample loop does not
+    // produce any calls while there is no output.  The thread stops
+    // when the sink is dropped.
+    pub fn idle(out: W, timeout: Duration) -> Sink<W> {
+        let sink = Sink::new(out, Flush::Idle(timeout));
+        let weak = Arc::downgrade(&sink.state);
+        thread::spawn(move || idle_flusher(weak, timeout));
+        sink
+    }
+}
+
+fn idle_flusher<W: Write>(state: Weak<Mutex<SinkState<W>>>, timeout: Duration) {
+    let tick = (timeout / 2).max(Duration::from_millis(1));
+    loop {
+        thread::sleep(tick);
+        let state = match state.upgrade() { Some(state) => state, None => return };
+        let mut state = state.lock().unwrap();
+        if !state.buf.is_empty() && state.last.elapsed() >= timeout {
+            // Returned by the next write or flush.
+            if let Err(err) = state.flush_buf() {
+                state.error = Some(err);
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        state.take_error()?;
+        if let Flush::Idle(timeout) = self.policy {
+            if state.last.elapsed() >= timeout { state.flush_buf()?; }
+        }
+        state.buf.extend_from_slice(data);
+        state.newline |= data.contains(&b'\n');
+        state.last = Instant::now();
+        let limit = match self.policy {
+            Flush::Bytes(n) => n,
+            _ => SINK_MAX,
+        };
+        if state.buf.len() >= limit { state.flush_buf()?; }
+        Ok(data.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.take_error()?;
+        match self.policy {
+            Flush::Item => state.flush_buf(),
+            Flush::Line if state.newline => state.flush_buf(),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Sink<W> {
+    fn drop(&mut self) {
+        let _ = self.state.lock().unwrap().flush_buf();
+    }
+}
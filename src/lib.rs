@@ -1,5 +1,21 @@
+extern crate zip;
+extern crate memmap2;
+extern crate libc;
+extern crate flate2;
+
 pub mod sm;
 pub mod io;
 pub mod mipmap;
-
-
+pub mod par;
+pub mod decoder;
+pub mod vcd;
+pub mod sigrok;
+pub mod output;
+pub mod pcap;
+pub mod erl;
+pub mod control;
+pub mod view;
+pub mod render;
+pub mod trigger;
+pub mod record;
+pub mod gen;
@@ -1,142 +1,871 @@
 /* Trampoline binary.
 
    It my current setup, it is simpler to use a single binary to host a
-   number of specific parsers.  Note that this is very ad-hoc, and
-   currently not very configurable.
+   number of specific parsers.  Parsers are looked up by name in the
+   decoder registry and configured with key=value arguments, e.g.
+
+     logan uart baudrate=9600 channel=2
+
+   Options can also be set in the environment, e.g. LOGAN_SAMPLERATE.
+   Use "logan list" to list the decoders, and "logan help <decoder>"
+   to list its options.
+
+   Options handled by the binary:
+
+     file=<path>       read capture from file instead of stdin
+     vcd=<path>        read samples from a VCD file, resampled at
+                       samplerate, e.g. simulation output
+     wires=<w,...>     VCD wires to use, in channel order
+     sr=<path>         read samples from a sigrok session file
+     format=<fmt>      output format: native (default), raw, hex,
+                       csv, jsonl or pcap.  See output.rs
+     pcap=<path>       write PCAPNG to file instead, e.g. for
+                       "logan slip --pcap slip.pcapng"
+     linktype=<type>   pcap link type: raw (default, IPv4/IPv6),
+                       ipv4, ipv6, user0-user15 or a number
+
+     flush=<policy>    output flushing: item, line, bytes:<n> or
+                       idle:<ms>.  Default is idle:20 for stdin and
+                       bytes:65536 for files
+     control=<path>    accept control commands on a Unix socket
+     control_fd=<n>    accept control commands on file descriptor n
+
+     trigger=<spec>    decode only around trigger events, see
+                       trigger.rs, e.g. trigger=1XR/uart:0x7e
+     pre=<n>           samples before the trigger (0)
+     post=<n>          samples after the trigger (1000)
+     count=<n>         stop after n triggers (0: no limit)
+
+     threads=<n>       number of threads for capture files
+     map=<c,...>       output channel i is input channel c[i]
+     invert=<bits>     invert channels, e.g. invert=0x81
+     mask=<bits>       set channels not in mask to 0
+     deglitch=<w,...>  suppress pulses shorter than w samples, per
+                       channel starting at channel 0
+
+   Remapping is done first, so all other channel numbers are logical
+   channel numbers.
+
+   The erl and etf formats produce {packet,4} frames for erl/logan.erl,
+   with start, eof and error messages in-band.
+
+   Several decoders can run on the same samples, e.g. "logan uart,spi".
+   Their options can be changed at run time through the control
+   channel, see control.rs.  This disables parallel decoding.
+
+   Protocol errors the decoder recovered from, e.g. UART framing
+   errors, are counted and reported at the end of the capture, except
+   when decoding in parallel.
+
+   Other commands:
+
+     logan vcd [names=a,b,..|channels=<n>] [decode=uart,spi,..]
+
+   Convert a capture to VCD on stdout, with decoder output added as
+   extra signals.  Decoders take their options from the same list.
+
+     logan sr <file.sr> [names=a,b,..|channels=<n>]
+
+   Convert a capture to a sigrok session file, e.g. for PulseView.
+
+     logan mipmap <store> file=<capture> [channels=<n>]
+
+   Build a file-backed mipmap store for zooming, see mipmap.rs
+
+     logan record <prefix> trigger=<spec> [pre=<n>] [post=<n>]
+                  [format=raw|vcd|sr] [files=<n>] [quota=<size>]
+
+   Write pre samples before and post samples after each trigger event
+   to a new file <prefix>-<seq>-<sample>.<ext>, e.g. on a live stream
+   from stdin.  The oldest files are removed to keep at most n files
+   and at most size bytes in total, e.g. quota=500M.  See record.rs
+
+     logan generate <uart|slip|spi|i2c> words=<w,..>|text=<string>
+                    [file=<path>] [repeat=<n>]
+
+   Write a capture of the encoded words to stdout or a file, one byte
+   per sample.  Options are those of the decoder, so the capture
+   decodes with the same arguments.  SPI and I2C clocks run at
+   baudrate (100000).  Extra options for uart and slip: parity=
+   none|even|odd, stop=<bits> and idle=<bits> before each frame.
+   I2C uses scl=<ch> (0) and sda=<ch> (1); for I2C and SLIP the words
+   are a single transaction or packet.  SPI truncate=<n> drops the
+   last n bits of the last word.
+
+   Faults, on the channels carrying signals: rate_error=<fraction>
+   and drift=<per sample> of the transmitter clock, jitter=<n> of
+   edges in samples, glitch=<p> and flip=<p> probability per sample
+   of a 1 sample pulse or of flip_width=<n> samples inverted, and
+   skip=<n> samples at the start.  Random choices follow seed=<n>.
+   See gen.rs
+
+     logan measure [channels=<n>] [window=<n> [step=<n>]] [hist=1]
+
+   Print edge counts, frequency, duty cycle and min/mean/max of the
+   period and pulse widths per channel.  With window=, a report of the
+   last n samples is printed every step samples.  hist=1 adds log2
+   histograms.
+
+     logan view [store=<store>] [names=a,b,..|channels=<n>]
+                [decode=uart,spi,..] [once=1]
+
+   Browse a capture in the terminal.  Without store=, a mipmap is
+   built in memory from the samples.  Decoder output is shown below
+   the waveforms.  Keys: h/l or left/right pan, +/- or up/down zoom,
+   g/G start/end, q quit.  If stdout is not a terminal or once=1 is
+   given, a single screen showing the whole capture is printed.
+
+     logan render <file.svg|file.png> [from=<n>] [to=<n>] [width=<px>]
+                  [names=a,b,..|channels=<n>] [decode=uart,spi,..]
+
+   Render samples from..to as an image with a time axis, for reports.
+   Sources and decoders are selected as for view.  See render.rs
 */
 
 extern crate logan;
 extern crate derive_more;
+extern crate memmap2;
 
-use logan::sm::{uart,slip,syncser,diff,apply};
-use logan::io::{stdin8,write_byte};
+use logan::sm::{self,remap,deglitch,measure,apply,Push};
+use logan::io::{stdin8,load,Sink,Flush};
+use logan::sm::remap::Remap;
+use logan::sm::deglitch::Deglitch;
+use logan::decoder::{self,Registry,Options,Item,Decoder,Output};
+use logan::{par,vcd,sigrok,output,pcap,control,mipmap,view,render,trigger,record,gen};
+use std::io::{BufReader,Write};
+use std::time::Duration;
+use std::fs::File;
+use std::convert::TryFrom;
+use std::ops::Deref;
+use memmap2::Mmap;
 use derive_more::From;
 
-fn start_uart() -> Result<(), AppError>  {
-    let mut uart = uart::init(uart::Config {
-        period:  samplerate()? / baudrate()?,
-        nb_bits: 8,
-        channel: 0,
+/* Preprocessing stages are pass-through when not configured. */
+fn preprocessing(options: &Options) -> Result<(Remap, Deglitch), AppError> {
+    let remap = remap::init(remap::Config {
+        map:    options.list("map")?,
+        invert: options.usize("invert", 0)?,
+        mask:   options.usize("mask", !0)?,
     });
-    // uart::test(&mut uart);
-    for b in apply(&mut uart, stdin8()) {
-        //println!("{}", (b as u8) as char);
-        write_byte(b as u8);
+    let widths = options.list("deglitch")?.unwrap_or_default();
+    if widths.len() > usize::BITS as usize {
+        return Err(decoder::Error::BadOption("deglitch".to_string(), "too many channels".to_string()).into());
     }
-    Ok(())
+    let deglitch = deglitch::init(deglitch::Config { widths });
+    Ok((remap, deglitch))
+}
 
+/* Capture with one byte per sample, mapped from a file or read from
+   a session.  Sessions with wider samples are read through samples(). */
+enum Capture {
+    File(Mmap),
+    Session(Vec<u8>),
 }
 
-fn start_slip() -> Result<(), AppError> {
+impl Deref for Capture {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match *self {
+            Capture::File(ref map) => map,
+            Capture::Session(ref data) => data,
+        }
+    }
+}
 
-    let baud = 115200usize;
+fn capture(options: &Options) -> Result<Option<Capture>, AppError> {
+    if let Some(path) = options.str("sr") {
+        let session = sigrok::read(&path)?;
+        if session.unitsize == 1 {
+            return Ok(Some(Capture::Session(session.data)));
+        }
+        return Ok(None);
+    }
+    match options.str("file") {
+        Some(path) => Ok(Some(Capture::File(load(&path)?))),
+        None => Ok(None),
+    }
+}
 
-    let mut slip = slip::init(slip::Config {
-        end: 0x0D,
-        esc: 0x0C,
-        esc_end: 0x0B,
-        esc_esc: 0x0A,
-    });
-    
-    let mut uart = uart::init(uart::Config {
-        period:  samplerate()? / baud,
-        nb_bits: 8,
-        channel: 0,
+/* Sample source, for modes where raw speed is less important.  A VCD
+   file is parsed while iterating: a read error ends the iteration
+   early, and is returned by check() afterwards. */
+enum Samples {
+    Vcd(Box<vcd::Reader<BufReader<File>>>),
+    Other(Box<dyn Iterator<Item=usize>>),
+}
+
+impl Iterator for Samples {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        match *self {
+            Samples::Vcd(ref mut reader) => reader.next(),
+            Samples::Other(ref mut samples) => samples.next(),
+        }
+    }
+}
+
+impl Samples {
+    fn check(&self) -> Result<(), AppError> {
+        match *self {
+            Samples::Vcd(ref reader) => match reader.error() {
+                Some(e) => Err(std::io::Error::new(e.kind(), e.to_string()).into()),
+                None => Ok(()),
+            },
+            Samples::Other(_) => Ok(()),
+        }
+    }
+}
+
+fn samples(options: &Options) -> Result<Samples, AppError> {
+    if let Some(path) = options.str("vcd") {
+        let file = BufReader::new(File::open(&path)?);
+        let wires: Vec<String> = match options.str("wires") {
+            Some(wires) => wires.split(',').map(|w| w.to_string()).collect(),
+            None => vec![],
+        };
+        let samplerate = options.usize("samplerate", 2000000)?;
+        return Ok(Samples::Vcd(Box::new(vcd::Reader::new(file, &wires, samplerate)?)));
+    }
+    if let Some(path) = options.str("sr") {
+        let session = sigrok::read(&path)?;
+        return Ok(Samples::Other(Box::new(session.samples().collect::<Vec<_>>().into_iter())));
+    }
+    match capture(options)? {
+        Some(capture) => Ok(Samples::Other(Box::new((0..capture.len()).map(move |i| capture[i] as usize)))),
+        None => Ok(Samples::Other(Box::new(stdin8().map(|b| b as usize)))),
+    }
+}
+
+fn start_decoder(registry: &Registry, name: &str, options: &Options) -> Result<(), AppError> {
+    let mut decoder = registry.create(name, options)?;
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let preprocess = !remap.is_identity() || options.str("deglitch").is_some();
+    let capture = capture(options)?;
+    let sequential = capture.is_none() || preprocess;
+    let mut out = output(name, options)?;
+    out.status("start")?;
+
+    /* If a capture file is given instead of using stdin, decoders that
+       support it run in parallel.  This is only done when there is no
+       preprocessing. */
+    let pre = (&mut remap).then(&mut deglitch);
+    let rv = match capture {
+        Some(capture) =>
+            if preprocess {
+                run(&mut decoder, pre, capture.iter().map(|&b| b as usize), &mut out)
+            }
+            else {
+                let threads = options.usize("threads", par::nb_threads())?;
+                let init = || registry.create(name, options).expect("decoder");
+                par::stamped(&capture, threads, init).into_iter()
+                    .try_for_each(|(t, item)| out.item(t, &item))
+                    .map_err(AppError::from)
+            },
+        None =>
+            samples(options).and_then(|mut samples| {
+                run(&mut decoder, pre, samples.by_ref(), &mut out)?;
+                samples.check()
+            }),
+    };
+    /* Protocol errors are not available for the parallel decoders,
+       which are created per chunk. */
+    let errors = if sequential { error_report(&decoder.errors()) } else { None };
+    finish(&mut out, rv, options, &deglitch, errors.into_iter().collect())
+}
+
+/* Errors are also reported in-band, for the erl formats.  After a
+   successful run, the deglitch report and reports of protocol errors
+   the decoders recovered from are printed before eof. */
+fn finish<W: Write>(out: &mut output::Writer<W>, rv: Result<(), AppError>, options: &Options,
+                    deglitch: &Deglitch, errors: Vec<String>) -> Result<(), AppError> {
+    if let Err(ref err) = rv {
+        let _ = out.error(&err.to_string());
+        return rv;
+    }
+    if options.str("deglitch").is_some() {
+        let report = deglitch.report();
+        eprintln!("{}", report);
+        out.status(&report)?;
+    }
+    for report in errors.iter() {
+        eprintln!("{}", report);
+        out.status(report)?;
+    }
+    out.status("eof")?;
+    Ok(())
+}
+
+fn error_report(errors: &[(&'static str, usize)]) -> Option<String> {
+    if errors.iter().any(|&(_, n)| n > 0) { Some(sm::report(errors)) } else { None }
+}
+
+/* Decode only around trigger events.  The decoder is flushed and reset
+   at the start of each segment, so it does not combine samples from
+   different segments. */
+fn start_triggered(registry: &Registry, name: &str, spec: &str, options: &Options) -> Result<(), AppError> {
+    let mut decoder = registry.create(name, options)?;
+    let mut gate = trigger::gate(trigger::parse(spec, registry, options)?, trigger::Config {
+        pre: options.usize("pre", 0)?,
+        post: options.usize("post", 1000)?,
+        count: options.usize("count", 0)?,
     });
-     
-    for packet in apply(&mut slip,
-                  apply(&mut uart,
-                        stdin8())) {
-        slip::print(packet);
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let mut out = output(name, options)?;
+    out.status("start")?;
+    let mut next = None;
+    let mut samples = samples(options)?;
+    for (t, bus) in trigger::apply(&mut gate, apply(&mut pre, samples.by_ref())) {
+        if next != Some(t) {
+            if let Some(end) = next {
+                while let Some(item) = decoder.flush() { out.item(end, &item)?; }
+            }
+            decoder.reset();
+            out.status(&format!("segment {}", t))?;
+        }
+        next = Some(t + 1);
+        if let Some(item) = decoder.push(bus) { out.item(t, &item)?; }
+    }
+    samples.check()?;
+    if let Some(end) = next {
+        while let Some(item) = decoder.flush() { out.item(end, &item)?; }
+    }
+    out.status("eof")?;
+    Ok(())
+}
+
+/* Save windows around trigger events, e.g. on a live stream. */
+fn start_record(registry: &Registry, prefix: &str, options: &Options) -> Result<(), AppError> {
+    let spec = match options.str("trigger") {
+        Some(spec) => spec,
+        None => return Err(AppError::AppStrError("record: needs trigger=<spec>")),
+    };
+    let config = trigger::Config {
+        pre: options.usize("pre", 0)?,
+        post: options.usize("post", 1000)?,
+        count: options.usize("count", 0)?,
+    };
+    let mut gate = trigger::gate(trigger::parse(&spec, registry, options)?, config);
+    let format = options.str("format").unwrap_or_else(|| "raw".to_string());
+    let format = match record::Format::parse(&format) {
+        Some(format) => format,
+        None => return Err(decoder::Error::BadOption("format".to_string(), format).into()),
+    };
+    let quota = match options.str("quota") {
+        None => 0,
+        Some(quota) => match record::parse_size(&quota) {
+            Some(quota) => quota,
+            None => return Err(decoder::Error::BadOption("quota".to_string(), quota).into()),
+        },
+    };
+    let mut recorder = record::init(record::Config {
+        prefix: prefix.to_string(),
+        format,
+        samplerate: options.usize("samplerate", 2000000)?,
+        names: channel_names(options)?,
+        max_samples: config.pre + config.post + 1,
+        max_files: options.usize("files", 0)?,
+        quota,
+    })?;
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let mut samples = samples(options)?;
+    for (t, bus) in trigger::apply(&mut gate, apply(&mut pre, samples.by_ref())) {
+        if let Some(path) = recorder.push(t, bus)? {
+            eprintln!("record: {}", path.display());
+        }
+    }
+    samples.check()?;
+    if let Some(path) = recorder.flush()? {
+        eprintln!("record: {}", path.display());
     }
     Ok(())
 }
 
-/* Illustrating SPI for a slightly more involved example: booting a
-   iCE40 FPGA.  This involves multiple signals.
+/* Several decoders on the same samples, optionally controlled at run
+   time.  This always runs sequentially. */
+fn start_session(registry: &Registry, names: &str, options: &Options) -> Result<(), AppError> {
+    let names: Vec<String> = names.split(',').map(|n| n.to_string()).collect();
+    let mut session = control::Session::new(registry, &names, options)?;
+    let requests = match (options.str("control"), options.str("control_fd")) {
+        (Some(path), _) => Some(control::listen(&path)?),
+        (None, Some(fd)) => match i32::try_from(options.usize("control_fd", 0)?) {
+            Ok(fd) => Some(control::from_fd(fd)?),
+            Err(_) => return Err(decoder::Error::BadOption("control_fd".to_string(), fd).into()),
+        },
+        (None, None) => None,
+    };
+    /* All decoders share one writer, so items stay in time order and
+       the file formats have a single header. */
+    let mut out = output(&names[0], options)?;
+    out.status("start")?;
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let rv = {
+        let mut write = |slot: usize, t: usize, item: Item| {
+            out.set_decoder(&names[slot]);
+            out.item(t, &item)
+        };
+        samples(options).and_then(|mut samples| {
+            for bus in apply(&mut pre, samples.by_ref()) {
+                session.push(bus, &mut write)?;
+                if let Some(ref requests) = requests {
+                    if session.samples % 4096 == 0 { session.poll(requests); }
+                }
+            }
+            samples.check()?;
+            session.flush(&mut write)?;
+            Ok(())
+        })
+    };
+    let errors = session.slots.iter()
+        .filter_map(|slot| error_report(&slot.errors()).map(|r| format!("{} {}", slot.name, r)))
+        .collect();
+    finish(&mut out, rv, options, &deglitch, errors)
+}
+
+fn run<P,I,W>(decoder: &mut Box<dyn Decoder>, mut pre: P, samples: I,
+             out: &mut output::Writer<W>) -> Result<(), AppError>
+    where P: Push<usize,usize>,
+          I: Iterator<Item=usize>,
+          W: Write
+{
+    let mut decoder = decoder.stamp();
+    for (t, item) in apply(&mut decoder, apply(&mut pre, samples)) {
+        out.item(t, &item)?;
+    }
+    Ok(())
+}
 
-   GND                      (black)
+/* Output to a live stream is flushed when idle, so items are seen
+   promptly without a system call per item. */
+fn flush_policy(options: &Options) -> Result<Flush, AppError> {
+    match options.str("flush") {
+        Some(spec) => match Flush::parse(&spec) {
+            Some(flush) => Ok(flush),
+            None => Err(decoder::Error::BadOption("flush".to_string(), spec).into()),
+        },
+        None if options.str("file").is_some() || options.str("sr").is_some()
+            || options.str("vcd").is_some() => Ok(Flush::Bytes(65536)),
+        None => Ok(Flush::Idle(Duration::from_millis(20))),
+    }
+}
 
-   BBB SPI0 test
-   0
-   1
-   2 CDONE  gpio3_14 P9_31
-   3 CRESET gpio3_15 P9_29  (green)
-   4 MOSI (D1)       P9_18  (blue)
-   5 MISO (D0)       P9_21  (purple) 
-   6 SCLK            P9_22  (grey)
-   7 CS     gpio1_12 P8_12  (white)
-*/
+fn sink<W: Write+Send+'static>(out: W, flush: Flush) -> Sink<W> {
+    match flush {
+        Flush::Idle(timeout) => Sink::idle(out, timeout),
+        _ => Sink::new(out, flush),
+    }
+}
 
-fn start_ice40() -> Result<(), AppError>  {
-    let config = syncser::Config {
-        clock_channel:   5,
-        data_channel:    4,
-        frame_channel:   0,
-        clock_edge:      0,
-        clock_polarity:  0,
-        frame_enable:    true,
-        frame_active:    0,
-        frame_timeout:   0, //disabled
-        timeout_enable:  false,
-        nb_bits:         8
-    };
-    let mut syncser = syncser::init(config);
-    for b in apply(&mut syncser, stdin8()) {
-        println!("{:01$x}",b,2);
-        // logan::io::write_byte(b);
+/* Decoder output goes to stdout, or to a pcap file if requested. */
+fn output(name: &str, options: &Options) -> Result<output::Writer<Box<dyn Write>>, AppError> {
+    let flush = flush_policy(options)?;
+    let (format, out): (String, Box<dyn Write>) = match options.str("pcap") {
+        Some(path) => ("pcap".to_string(), Box::new(sink(File::create(&path)?, flush))),
+        None => (options.str("format").unwrap_or_else(|| "native".to_string()),
+                 Box::new(sink(std::io::stdout(), flush))),
+    };
+    let format = match output::Format::parse(&format) {
+        Some(output::Format::Pcap(linktype)) => match options.str("linktype") {
+            None => output::Format::Pcap(linktype),
+            Some(name) => match pcap::parse_linktype(&name) {
+                Some(linktype) => output::Format::Pcap(linktype),
+                None => return Err(decoder::Error::BadOption("linktype".to_string(), name).into()),
+            },
+        },
+        Some(format) => format,
+        None => return Err(decoder::Error::BadOption("format".to_string(), format).into()),
+    };
+    Ok(output::init(output::Config {
+        format,
+        decoder: name.to_string(),
+        samplerate: options.usize("samplerate", 2000000)?,
+        word_bytes: options.usize("bits", 8)?.div_ceil(8),
+    }, out)?)
+}
+
+/* Convert capture to VCD on stdout, optionally with decoder output. */
+fn start_vcd(registry: &Registry, options: &Options) -> Result<(), AppError> {
+    let samplerate = options.usize("samplerate", 2000000)?;
+    let names = channel_names(options)?;
+    let out = sink(std::io::stdout(), flush_policy(options)?);
+    let mut vcd = vcd::Writer::new(out, samplerate, &names);
+
+    let mut decoders = vec![];
+    if let Some(decode) = options.str("decode") {
+        for name in decode.split(',') {
+            let entry = match registry.get(name) {
+                Some(entry) => entry,
+                None => return Err(decoder::Error::UnknownDecoder(name.to_string()).into()),
+            };
+            let kind = match entry.output {
+                Output::Words => vcd::Kind::Vector(options.usize("bits", 8)?),
+                _ => vcd::Kind::String,
+            };
+            let signal = vcd.add(name, kind);
+            decoders.push(((entry.new)(options)?, signal));
+        }
+    }
+
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let mut t = 0;
+    let mut samples = samples(options)?;
+    for bus in apply(&mut pre, samples.by_ref()) {
+        vcd.sample(t, bus)?;
+        for (decoder, signal) in decoders.iter_mut() {
+            if let Some(item) = decoder.push(bus) {
+                vcd_item(&mut vcd, t, *signal, item)?;
+            }
+        }
+        t += 1;
+    }
+    samples.check()?;
+    for (decoder, signal) in decoders.iter_mut() {
+        while let Some(item) = decoder.flush() {
+            vcd_item(&mut vcd, t, *signal, item)?;
+        }
     }
+    vcd.finish(t)?;
     Ok(())
 }
 
-fn start_diff() -> Result<(), AppError>  {
-    let mut diff = diff::init();
-    for b in apply(&mut diff, stdin8()) {
-        println!("{:01$x}",b,2);
-        // logan::io::write_byte(b);
+/* Convert capture to a sigrok session file. */
+fn start_sr(path: &str, options: &Options) -> Result<(), AppError> {
+    let mut session = sigrok::Session::new(
+        options.usize("samplerate", 2000000)?, channel_names(options)?)?;
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let mut samples = samples(options)?;
+    for bus in apply(&mut pre, samples.by_ref()) {
+        session.push(bus);
     }
+    samples.check()?;
+    sigrok::write(path, &session)?;
     Ok(())
 }
 
+/* Build a file-backed mipmap store from a capture file. */
+fn start_mipmap(path: &str, options: &Options) -> Result<(), AppError> {
+    let capture = match options.str("file") {
+        Some(capture) => capture,
+        None => return Err(AppError::AppStrError("mipmap: needs file=<capture>")),
+    };
+    let store: mipmap::Store<u8> = mipmap::create_from_capture(
+        path, &capture,
+        options.usize("channels", 8)?,
+        options.usize("samplerate", 2000000)?)?;
+    eprintln!("{}: {} samples, {} levels", path, store.len(), store.nb_levels());
+    Ok(())
+}
+
+/* Mipmap, channel names and decoder output for the viewers.  Decoders
+   need the samples, so these are read unless only a store is shown. */
+struct Waveforms {
+    store: mipmap::Store<u8>,
+    names: Vec<String>,
+    annotations: Vec<view::Annotation>,
+}
+
+fn waveforms(registry: &Registry, options: &Options) -> Result<Waveforms, AppError> {
+    let decode: Vec<String> = match options.str("decode") {
+        Some(decode) => decode.split(',').map(|n| n.to_string()).collect(),
+        None => vec![],
+    };
+    let mut raw: Vec<u8> = vec![];
+    if options.str("store").is_none() || !decode.is_empty() {
+        let (mut remap, mut deglitch) = preprocessing(options)?;
+        let mut pre = (&mut remap).then(&mut deglitch);
+        let mut samples = samples(options)?;
+        raw = apply(&mut pre, samples.by_ref()).map(|bus| bus as u8).collect();
+        samples.check()?;
+    }
+    let (store, channels) = match options.str("store") {
+        Some(path) => {
+            let store: mipmap::Store<u8> = mipmap::open_read_only(&path)?;
+            let channels = store.channels();
+            (store, channels)
+        },
+        None => (mipmap::mipmap(&raw), 8),
+    };
+    let mut names = channel_names(options)?;
+    if options.str("names").is_none() {
+        names.truncate(options.usize("channels", channels)?);
+    }
+
+    let mut annotations = vec![];
+    for name in decode.iter() {
+        let decoder = registry.create(name, options)?;
+        let mut decoder = decoder.stamp();
+        let items = apply(&mut decoder, raw.iter().map(|&b| b as usize))
+            .map(|(t, item)| (t, view::label(&item))).collect();
+        annotations.push(view::Annotation { name: name.clone(), items });
+    }
+    Ok(Waveforms { store, names, annotations })
+}
+
+/* Write a generated capture, one byte per sample. */
+fn start_generate(name: &str, options: &Options) -> Result<(), AppError> {
+    let generator = gen::from_options(name, options)?;
+    if generator.channels() > 0xFF {
+        return Err(AppError::AppStrError("generate: channels need to be 0 to 7 for one byte per sample"));
+    }
+    let words = match (options.list("words")?, options.str("text")) {
+        (Some(data), _) => data,
+        (None, Some(text)) => text.bytes().map(|b| b as usize).collect(),
+        (None, None) => return Err(AppError::AppStrError("generate: needs words=<w,..> or text=<string>")),
+    };
+    let faults = gen::faults_from_options(options, generator.channels())?;
+    let mut bus = vec![];
+    for _ in 0..options.usize("repeat", 1)? {
+        bus.extend(generator.encode(&words));
+    }
+    let samples: Vec<u8> = faults.apply(&bus).into_iter().map(|b| b as u8).collect();
+    match options.str("file") {
+        Some(path) => std::fs::write(&path, &samples)?,
+        None => {
+            let mut out = Sink::new(std::io::stdout(), Flush::Item);
+            out.write_all(&samples)?;
+            out.flush()?;
+        },
+    }
+    Ok(())
+}
+
+/* Timing summary per channel, for the whole capture or a sliding
+   window. */
+fn start_measure(options: &Options) -> Result<(), AppError> {
+    let samplerate = options.usize("samplerate", 2000000)?;
+    let hist = options.usize("hist", 0)? != 0;
+    let channels = options.usize("channels", 8)?;
+    if channels > usize::BITS as usize {
+        return Err(decoder::Error::BadOption("channels".to_string(), "too many channels".to_string()).into());
+    }
+    let mut m = measure::init(measure::Config {
+        channels,
+        window: options.usize("window", 0)?,
+        step: options.usize("step", 0)?,
+    });
+    let (mut remap, mut deglitch) = preprocessing(options)?;
+    let mut pre = (&mut remap).then(&mut deglitch);
+    let mut out = sink(std::io::stdout(), flush_policy(options)?);
+    let mut samples = samples(options)?;
+    for report in apply(&mut m, apply(&mut pre, samples.by_ref())) {
+        write!(out, "{}", report.table(samplerate))?;
+        if hist { write!(out, "{}", report.histogram())?; }
+        out.flush()?;
+    }
+    samples.check()?;
+    Ok(())
+}
+
+/* Each name is a channel of the store's samples. */
+fn check_names(store: &mipmap::Store<u8>, names: &[String], options: &Options) -> Result<(), AppError> {
+    if names.len() > store.width() {
+        let key = if options.str("names").is_some() { "names" } else { "channels" };
+        return Err(decoder::Error::BadOption(
+            key.to_string(), format!("{} channels, store has {}", names.len(), store.width())).into());
+    }
+    Ok(())
+}
+
+/* Browse a capture in the terminal. */
+fn start_view(registry: &Registry, options: &Options) -> Result<(), AppError> {
+    let Waveforms { store, names, annotations } = waveforms(registry, options)?;
+    check_names(&store, &names, options)?;
+    let samplerate = match store.samplerate() {
+        0 => options.usize("samplerate", 2000000)?,
+        sr => sr,
+    };
+    let label = view::label_width(&names, &annotations);
+    let once = options.usize("once", 0)? != 0 || !view::is_tty();
+    let mut terminal = if once { None } else { Some(view::Terminal::open()?) };
+    let cols = match terminal {
+        Some(ref terminal) => terminal.size().0,
+        None => options.usize("width", 80)?,
+    };
+    let width = cols.saturating_sub(label + 1).max(1);
+    let mut window = view::Window {
+        start: 0, zoom: view::fit(store.len(), width), width, samplerate,
+    };
+    let max_zoom = window.zoom;
+    let mut out = Sink::new(std::io::stdout(), Flush::Item);
+    loop {
+        let lines = view::render(&store, &names, &annotations, &window);
+        match terminal {
+            None => {
+                for line in lines { writeln!(out, "{}", line)?; }
+                out.flush()?;
+                return Ok(());
+            },
+            Some(ref mut terminal) => {
+                write!(out, "\x1b[H\x1b[2J")?;
+                for line in lines { writeln!(out, "{}", line)?; }
+                out.flush()?;
+                match terminal.key()? {
+                    view::Key::Quit => break,
+                    key => window.apply(&key, store.len(), max_zoom),
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/* Render a time range to SVG or PNG, depending on the extension. */
+fn start_render(registry: &Registry, path: &str, options: &Options) -> Result<(), AppError> {
+    let width = options.usize("width", 1000)?;
+    if width == 0 {
+        return Err(decoder::Error::BadOption("width".to_string(), "needs to be at least 1".to_string()).into());
+    }
+    let Waveforms { store, names, annotations } = waveforms(registry, options)?;
+    check_names(&store, &names, options)?;
+    let samplerate = match store.samplerate() {
+        0 => options.usize("samplerate", 2000000)?,
+        sr => sr,
+    };
+    let config = render::Config {
+        from: options.usize("from", 0)?,
+        to: options.usize("to", store.len())?.min(store.len()),
+        width,
+        samplerate,
+    };
+    if config.from >= config.to {
+        return Err(AppError::AppStrError("render: empty range"));
+    }
+    let image = render::layout(&store, &names, &annotations, &config);
+    let mut out = std::io::BufWriter::new(File::create(path)?);
+    if path.ends_with(".png") {
+        render::png(&mut out, &image)?;
+    }
+    else {
+        render::svg(&mut out, &image)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/* One name per bus bit at most. */
+fn channel_names(options: &Options) -> Result<Vec<String>, AppError> {
+    let names: Vec<String> = match options.str("names") {
+        Some(names) => names.split(',').map(|n| n.to_string()).collect(),
+        None => {
+            let channels = options.usize("channels", 8)?;
+            if channels > usize::BITS as usize {
+                return Err(decoder::Error::BadOption("channels".to_string(), "too many channels".to_string()).into());
+            }
+            (0..channels).map(|c| format!("d{}", c)).collect()
+        },
+    };
+    if names.len() > usize::BITS as usize {
+        return Err(decoder::Error::BadOption("names".to_string(), "too many channels".to_string()).into());
+    }
+    Ok(names)
+}
+
+fn vcd_item<W: Write>(vcd: &mut vcd::Writer<W>, t: u64, signal: usize, item: Item) -> std::io::Result<()> {
+    match item {
+        Item::Byte(b)   => vcd.string(t, signal, &(b as char).to_string()),
+        Item::Word(w)   => vcd.word(t, signal, w),
+        Item::Packet(p) => {
+            let hex: Vec<String> = p.iter().map(|b| format!("{:02x}", b)).collect();
+            vcd.string(t, signal, &hex.join(""))
+        }
+    }
+}
+
+fn list(registry: &Registry) -> Result<(), AppError> {
+    for e in registry.entries() {
+        println!("{:10} {}", e.name, e.description);
+    }
+    Ok(())
+}
+
+fn help(registry: &Registry, name: &str) -> Result<(), AppError> {
+    match registry.get(name) {
+        None => Err(decoder::Error::UnknownDecoder(name.to_string()).into()),
+        Some(e) => {
+            println!("{}: {}", e.name, e.description);
+            for (key, help) in e.options {
+                println!("  {:12} {}", key, help);
+            }
+            Ok(())
+        }
+    }
+}
 
 fn start() -> Result<(), AppError> {
     let args : Vec<String> = std::env::args().collect() ;
+    let registry = Registry::builtin();
+    if args.len() < 2 {
+        return Err(AppError::AppStrError("usage: logan <decoder> [key=value ...]"));
+    }
     match &(args[1])[..] {
-        "uart"  => start_uart(),
-        "slip"  => start_slip(),
-        "ice40" => start_ice40(),
-        "diff"  => start_diff(),
-        _ => Err(AppError::AppStrError("Unknown type"))
+        "list" => list(&registry),
+        "vcd"  => start_vcd(&registry, &Options::parse(args[2..].iter().cloned())?),
+        "sr"   => match args.get(2) {
+            Some(path) => start_sr(path, &Options::parse(args[3..].iter().cloned())?),
+            None => Err(AppError::AppStrError("usage: logan sr <file.sr> [key=value ...]")),
+        },
+        "mipmap" => match args.get(2) {
+            Some(path) => start_mipmap(path, &Options::parse(args[3..].iter().cloned())?),
+            None => Err(AppError::AppStrError("usage: logan mipmap <store> file=<capture>")),
+        },
+        "render" => match args.get(2) {
+            Some(path) => start_render(&registry, path, &Options::parse(args[3..].iter().cloned())?),
+            None => Err(AppError::AppStrError("usage: logan render <file.svg|file.png> [key=value ...]")),
+        },
+        "record" => match args.get(2) {
+            Some(prefix) => start_record(&registry, prefix, &Options::parse(args[3..].iter().cloned())?),
+            None => Err(AppError::AppStrError("usage: logan record <prefix> trigger=<spec> [key=value ...]")),
+        },
+        "generate" => match args.get(2) {
+            Some(name) => start_generate(name, &Options::parse(args[3..].iter().cloned())?),
+            None => Err(AppError::AppStrError("usage: logan generate <uart|slip|spi|i2c> [key=value ...]")),
+        },
+        "measure" => start_measure(&Options::parse(args[2..].iter().cloned())?),
+        "view" => start_view(&registry, &Options::parse(args[2..].iter().cloned())?),
+        "help" => match args.get(2) {
+            Some(name) => help(&registry, name),
+            None => Err(AppError::AppStrError("usage: logan help <decoder>")),
+        },
+        name => {
+            let options = Options::parse(args[2..].iter().cloned())?;
+            if name.contains(',') || options.str("control").is_some()
+                || options.str("control_fd").is_some() {
+                start_session(&registry, name, &options)
+            }
+            else if let Some(spec) = options.str("trigger") {
+                start_triggered(&registry, name, &spec, &options)
+            }
+            else {
+                start_decoder(&registry, name, &options)
+            }
+        }
     }
 }
 fn main() {
-    match start() {
-        Ok(()) => (),
-        _ => panic!("Error") // FIXME
+    if let Err(err) = start() {
+        eprintln!("logan: {}", err);
+        std::process::exit(1);
     }
 }
 
 // To handle multiple errors, put them in an Enum like this
 #[derive(From)]
+#[allow(clippy::enum_variant_names)]
 enum AppError {
-    AppVarError(std::env::VarError),
-    AppParseIntError(std::num::ParseIntError),
+    AppIoError(std::io::Error),
+    AppDecoderError(decoder::Error),
     AppStrError(& 'static str)
 }
-
-/* Some shared code. */
-
-
-fn samplerate() -> Result<usize, AppError> { var("LOGAN_SAMPLERATE", 2000000) }
-fn baudrate()   -> Result<usize, AppError> { var("LOGAN_BAUDRATE",    115200) }
-fn var(varname: &str, default: usize) -> Result<usize, AppError> {
-    // let sr_str = std::env::var("LOGAN_SAMPLERATE")?;
-    match std::env::var(varname) {
-        Ok(sr_str) => {
-            let sr = sr_str.parse::<usize>()?;
-            Ok(sr)
-        },
-        Err(_) =>
-            Ok(default)
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AppError::AppIoError(e)      => write!(f, "{}", e),
+            AppError::AppDecoderError(e) => write!(f, "{}", e),
+            AppError::AppStrError(e)     => write!(f, "{}", e),
+        }
     }
 }
@@ -11,6 +11,13 @@ levels, reducing storage to only an extra faction 1/2^p
  */
 
 
+use memmap2::{Mmap,MmapMut};
+use std::fs::{File,OpenOptions};
+use std::io;
+use std::mem;
+use std::slice;
+use std::sync::{Arc,RwLock,RwLockReadGuard};
+
 /* Representation
 
 Each channel is two bits: [max|min]
@@ -24,11 +31,17 @@ That encoding allows bitwize-or to compute next mipmap.
 
 Buffer can be initialized from two complementary bit frames.
 
+The bits are stored in two planes, each holding one bit per channel
+in a sample-sized word: the min plane (low seen) and the max plane
+(high seen).
+
  */
 
-pub trait MipMap: Sized {
+pub trait MipMap: Sized+Copy {
     fn plane_init(&self) -> (Self, Self);      // orig -> level 0
     fn plane_or(&self, other: &Self) -> Self;  // level n -> level n + 1
+    fn plane_zero() -> Self;                   // neither
+    fn plane_bit(&self, c: usize) -> bool;
 }
 // Is there a trait to abstract logic operations instead of this workaround?
 macro_rules! impl_MipMap {
@@ -42,6 +55,12 @@ macro_rules! impl_MipMap {
             fn plane_or(&self, other: &$t) -> $t {
                 (*self) | (*other)
             }
+            fn plane_zero() -> $t {
+                0
+            }
+            fn plane_bit(&self, c: usize) -> bool {
+                ((*self) >> c) & 1 == 1
+            }
         });
 }
 impl_MipMap!(usize);
@@ -88,63 +107,419 @@ fn level_offset(index: usize, level: usize, nb_levels: usize) -> usize {
     let mask       = (1 << nb_levels) - 1;
     let left_ones  = (!0) << (nb_levels - level + 1);
     let trunc_addr = (index & mask) >> level;
-    let addr       = (left_ones | trunc_addr) & mask;
-    addr
+    (left_ones | trunc_addr) & mask
 }
 
 #[inline(always)]
 fn level_o_n(level: usize, nb_levels: usize) -> (usize, usize) {
     let o = level_offset(0, level, nb_levels);
     let n = 1 << (nb_levels - level);
-    println!("{} {:x} {}", level, o, n);
     (o,n)
 }
 
 /* Store increments in powers of two.  Mipmap is stored as an
 array of arrays.  Unused space is filled with 'neither'. */
 
-/* FIXME: Wanted to abstract this with slices but can't borrow the
-same array twice? */
+/* Levels are stored in increasing address order, so the fine level
+can be borrowed next to the coarse level by splitting the plane. */
 
-/* Precond: level sizes are correct. */
+/* Recompute coarse cells c_from..c_to from the level below.
+Precond: level sizes are correct. */
 #[inline(always)]
-fn build_single<M>(store: &mut [M], level: usize) where M: MipMap {
-    let nb_levels = store.len();  // needs to be power of 2.
+fn build_cells<M>(plane: &mut [M], level: usize, nb_levels: usize,
+                  c_from: usize, c_to: usize) where M: MipMap {
     // o: offset, n: number of elements
     // c: coarse, f: fine
-    let (f_o, _)   = level_o_n(level-1, nb_levels);
-    let (c_o, c_n) = level_o_n(level,   nb_levels);
-    for c_i in 0..c_n {
-        let f_i = 2 * c_i;
-        store[c_o + c_i] =
-            MipMap::plane_or(&store[f_o + f_i],
-                             &store[f_o + f_i + 1] );
+    let (f_o, _) = level_o_n(level-1, nb_levels);
+    let (c_o, _) = level_o_n(level,   nb_levels);
+    let (fine, coarse) = plane.split_at_mut(c_o);
+    for (c_i, c) in coarse[c_from..c_to].iter_mut().enumerate() {
+        let f_i = 2 * (c_from + c_i);
+        *c = MipMap::plane_or(&fine[f_o + f_i],
+                              &fine[f_o + f_i + 1] );
     }
 }
 
+/* Per channel summary of a range of samples. */
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Value {
+    Neither,
+    Low,
+    High,
+    Both,
+}
+
+/* Both planes for a capture of up to 2^N samples.  Public level
+numbers start at 0 for the original samples, up to N for a single
+summary of the whole capture.  Internally this is the address layout
+above with N+1 bits, where public level l is layout level l+1.
+
+Captures of arbitrary length are padded with 'neither'.  A capture
+can consist of several segments, e.g. triggered acquisitions,
+separated by gaps that are also 'neither'.  Actual samples are always
+either low or high, so 'neither' at level 0 marks absence of data.
+
+Planes are held in memory, or in a memory-mapped file so captures
+larger than RAM can be handled.  The file starts with a header,
+followed by the min and max planes in native byte order:
+
+  0  magic "LOGANMIP"
+  8  version      u32
+  12 channels     u32
+  16 sample width u32, bytes
+  20 levels       u32, N
+  24 samplerate   u64
+  32 length       u64, samples
+  40 segments     u32, count
+  44 reserved, up to 64
+  64 segment table, start and end as u64, MAX_SEGMENTS entries
+*/
+pub struct Store<M> {
+    nb_levels: usize,  // N
+    len: usize,        // samples written, up to 2^N
+    segments: Vec<(usize,usize)>,  // start, end
+    open: bool,        // append extends the last segment
+    channels: usize,
+    samplerate: usize,
+    planes: Planes<M>,
+}
+
+enum Planes<M> {
+    Memory(Vec<M>, Vec<M>),
+    Mapped(MmapMut),
+    ReadOnly(Mmap),
+}
+
+const MAGIC: &[u8; 8] = b"LOGANMIP";
+const VERSION: u32 = 2;
+const MAX_SEGMENTS: usize = 1024;
+const HEADER: usize = 64 + 16 * MAX_SEGMENTS;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
 /* first level -> compute from raw data. */
-pub fn mipmap<M>(store: &mut [M]) where M: MipMap {
-    /* Build first level from store. */
-    // TODO: create 2 bit planes
+pub fn mipmap<M>(raw: &[M]) -> Store<M> where M: MipMap {
+    let mut store = with_capacity(log2_upper(raw.len().max(1)));
+    store.append(raw);
+    store
+}
 
-    /* Recursively build other levels. */
-    let levels = log2_upper(store.len());
-    for level in 1..levels {
-        build_single(store, level);
-        // TODO: second bit plane
+/* Segments separated by gaps of the given number of samples. */
+pub fn concat<M>(segments: &[&[M]], gap: usize) -> Store<M> where M: MipMap {
+    let total: usize = segments.iter().map(|s| s.len()).sum::<usize>()
+        + gap * segments.len().saturating_sub(1);
+    let mut store = with_capacity(log2_upper(total.max(1)));
+    for (i, segment) in segments.iter().enumerate() {
+        if i > 0 { store.gap(gap).expect("memory store"); }
+        store.append(segment);
     }
+    store
 }
 
-/*
+/* Empty store for up to 2^N samples, filled by append. */
+pub fn with_capacity<M>(nb_levels: usize) -> Store<M> where M: MipMap {
+    let size = 2 << nb_levels;
+    Store {
+        nb_levels,
+        len: 0,
+        segments: vec![],
+        open: false,
+        channels: 8 * mem::size_of::<M>(),
+        samplerate: 0,
+        planes: Planes::Memory(vec![M::plane_zero(); size], vec![M::plane_zero(); size]),
+    }
+}
 
-TODO:
-- use disk-backed memory-mapped buffer
-- fix number of channels
-- fix maximum buffer size
+/* Build a file-backed store. */
+pub fn create<M>(path: &str, raw: &[M], channels: usize, samplerate: usize) -> io::Result<Store<M>>
+    where M: MipMap
+{
+    let nb_levels = log2_upper(raw.len().max(1));
+    let mut store = create_empty(path, nb_levels, channels, samplerate)?;
+    store.append(raw);
+    if let Planes::Mapped(ref map) = store.planes { map.flush()?; }
+    Ok(store)
+}
 
--> this makes on-the fly updates really simple + leaves memory
-   management to the OS.
+/* Empty file-backed store for up to 2^N samples. */
+pub fn create_empty<M>(path: &str, nb_levels: usize, channels: usize, samplerate: usize) -> io::Result<Store<M>>
+    where M: MipMap
+{
+    let plane_bytes = (2 << nb_levels) * mem::size_of::<M>();
+    let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+    file.set_len((HEADER + 2 * plane_bytes) as u64)?;
+    let mut map = unsafe { MmapMut::map_mut(&file)? };
+    {
+        let header = &mut map[..HEADER];
+        header[0..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(channels as u32).to_le_bytes());
+        header[16..20].copy_from_slice(&(mem::size_of::<M>() as u32).to_le_bytes());
+        header[20..24].copy_from_slice(&(nb_levels as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&(samplerate as u64).to_le_bytes());
+    }
+    // A new file reads as zero, which is 'neither' in both planes.
+    Ok(Store {
+        nb_levels, len: 0, segments: vec![], open: false,
+        channels, samplerate, planes: Planes::Mapped(map)
+    })
+}
 
- */
+/* Build a file-backed store from a capture file, which is mapped as
+well instead of loaded. */
+pub fn create_from_capture<M>(path: &str, capture: &str, channels: usize, samplerate: usize) -> io::Result<Store<M>>
+    where M: MipMap
+{
+    let file = File::open(capture)?;
+    let map = unsafe { Mmap::map(&file)? };
+    let n = map.len() / mem::size_of::<M>();
+    // Mapped memory is page aligned.
+    let raw = unsafe { slice::from_raw_parts(map.as_ptr() as *const M, n) };
+    create(path, raw, channels, samplerate)
+}
+
+/* Reopen a file-backed store without recomputation. */
+pub fn open<M>(path: &str) -> io::Result<Store<M>> where M: MipMap {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let map = unsafe { MmapMut::map_mut(&file)? };
+    from_map(path, Planes::Mapped(map))
+}
 
+/* Reopen for reading only, e.g. for a viewer.  Appending adds no
+samples and gap fails. */
+pub fn open_read_only<M>(path: &str) -> io::Result<Store<M>> where M: MipMap {
+    let file = File::open(path)?;
+    let map = unsafe { Mmap::map(&file)? };
+    from_map(path, Planes::ReadOnly(map))
+}
 
+fn from_map<M>(path: &str, planes: Planes<M>) -> io::Result<Store<M>> where M: MipMap {
+    let (nb_levels, len, segments, channels, samplerate) = {
+        let map: &[u8] = match planes {
+            Planes::Mapped(ref map) => map,
+            Planes::ReadOnly(ref map) => map,
+            Planes::Memory(..) => unreachable!(),
+        };
+        if map.len() < HEADER || &map[0..8] != MAGIC {
+            return Err(invalid(format!("mipmap: {}: not a mipmap store", path)));
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([map[i], map[i+1], map[i+2], map[i+3]]) as usize;
+        let u64_at = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&map[i..i+8]);
+            u64::from_le_bytes(bytes) as usize
+        };
+        let (samplerate, len) = (u64_at(24), u64_at(32));
+        let (version, channels, width, nb_levels) = (u32_at(8), u32_at(12), u32_at(16), u32_at(20));
+        if version != VERSION as usize {
+            return Err(invalid(format!("mipmap: {}: version {}", path, version)));
+        }
+        if width != mem::size_of::<M>() {
+            return Err(invalid(format!("mipmap: {}: sample width {}", path, width)));
+        }
+        if nb_levels >= usize::BITS as usize - 1
+            || map.len() != HEADER + 2 * (2 << nb_levels) * width {
+            return Err(invalid(format!("mipmap: {}: bad size", path)));
+        }
+        if len > 1 << nb_levels {
+            return Err(invalid(format!("mipmap: {}: bad length", path)));
+        }
+        let nb_segments = u32_at(40);
+        if nb_segments > MAX_SEGMENTS {
+            return Err(invalid(format!("mipmap: {}: bad segment table", path)));
+        }
+        let segments: Vec<(usize,usize)> = (0..nb_segments).map(|i| (u64_at(64 + 16 * i), u64_at(72 + 16 * i))).collect();
+        (nb_levels, len, segments, channels, samplerate)
+    };
+    // Appending to a reopened store continues the last segment,
+    // unless it ended with a gap.
+    let open = match segments.last() { Some(&(_, end)) => end == len, None => false };
+    Ok(Store {
+        nb_levels, len, segments, open,
+        channels, samplerate, planes
+    })
+}
+
+impl<M> Store<M> where M: MipMap {
+    fn planes(&self) -> (&[M], &[M]) {
+        match self.planes {
+            Planes::Memory(ref min, ref max) => (min, max),
+            Planes::Mapped(ref map) => mapped_planes(map),
+            Planes::ReadOnly(ref map) => mapped_planes(map),
+        }
+    }
+    fn planes_mut(&mut self) -> (&mut [M], &mut [M]) {
+        match self.planes {
+            Planes::Memory(ref mut min, ref mut max) => (min, max),
+            Planes::Mapped(ref mut map) => {
+                let n = (map.len() - HEADER) / (2 * mem::size_of::<M>());
+                let p = unsafe { map.as_mut_ptr().add(HEADER) } as *mut M;
+                unsafe { (slice::from_raw_parts_mut(p, n), slice::from_raw_parts_mut(p.add(n), n)) }
+            }
+            Planes::ReadOnly(_) => panic!("mipmap: read-only store"),
+        }
+    }
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+    /* 0 if unknown */
+    pub fn samplerate(&self) -> usize {
+        self.samplerate
+    }
+    /* Highest level, which has a single element. */
+    pub fn nb_levels(&self) -> usize {
+        self.nb_levels
+    }
+    /* Number of original samples. */
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /* Channels that fit in a sample, at least channels(). */
+    pub fn width(&self) -> usize {
+        8 * mem::size_of::<M>()
+    }
+    pub fn capacity(&self) -> usize {
+        1 << self.nb_levels
+    }
+    /* Start and end of the segments, in samples. */
+    pub fn segments(&self) -> &[(usize,usize)] {
+        &self.segments
+    }
+    fn set_len(&mut self, len: usize) {
+        self.len = len;
+        if let Planes::Mapped(ref mut map) = self.planes {
+            map[32..40].copy_from_slice(&(len as u64).to_le_bytes());
+            map[40..44].copy_from_slice(&(self.segments.len() as u32).to_le_bytes());
+            if let Some(&(start, end)) = self.segments.last() {
+                let i = 64 + 16 * (self.segments.len() - 1);
+                map[i..i+8].copy_from_slice(&(start as u64).to_le_bytes());
+                map[i+8..i+16].copy_from_slice(&(end as u64).to_le_bytes());
+            }
+        }
+    }
+    /* End the current segment and skip n samples, which read as
+    'neither'.  The next append starts a new segment.  File-backed
+    stores have a limited number of segments. */
+    pub fn gap(&mut self, n: usize) -> io::Result<()> {
+        match self.planes {
+            Planes::Mapped(_) if self.segments.len() >= MAX_SEGMENTS =>
+                return Err(invalid("mipmap: too many segments".to_string())),
+            Planes::ReadOnly(_) =>
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "mipmap: read-only store")),
+            _ => (),
+        }
+        self.open = false;
+        let len = (self.len + n).min(self.capacity());
+        self.set_len(len);
+        Ok(())
+    }
+    /* Add samples at the end, up to the capacity.  Only the cells
+    covering the new samples are updated, so this is O(log n) per
+    block on top of the block size.  Cells that are partially filled
+    summarize the samples written so far.  Returns the number of
+    samples added, which is 0 for a read-only store. */
+    pub fn append(&mut self, samples: &[M]) -> usize {
+        if let Planes::ReadOnly(_) = self.planes { return 0; }
+        let from = self.len;
+        let to = (from + samples.len()).min(self.capacity());
+        if to == from { return 0; }
+        if !self.open || self.segments.is_empty() {
+            self.segments.push((from, from));
+            self.open = true;
+        }
+        self.segments.last_mut().unwrap().1 = to;
+        let nb_levels = self.nb_levels;
+        {
+            let (min, max) = self.planes_mut();
+            for (i, sample) in (from..to).zip(samples.iter()) {
+                let (bit0, bit1) = sample.plane_init();
+                min[i] = bit0;
+                max[i] = bit1;
+            }
+            for level in 2..nb_levels+2 {
+                let (c_from, c_to) = (from >> (level-1), ((to-1) >> (level-1)) + 1);
+                build_cells(min, level, nb_levels + 1, c_from, c_to);
+                build_cells(max, level, nb_levels + 1, c_from, c_to);
+            }
+        }
+        self.set_len(to);
+        to - from
+    }
+    /* Min and max planes of a level.  Element i summarizes samples
+    i*2^level up to (i+1)*2^level. */
+    pub fn level(&self, level: usize) -> (&[M], &[M]) {
+        assert!(level <= self.nb_levels);
+        let (o, n) = level_o_n(level + 1, self.nb_levels + 1);
+        let (min, max) = self.planes();
+        (&min[o..o+n], &max[o..o+n])
+    }
+    pub fn get(&self, level: usize, index: usize) -> (M, M) {
+        let (min, max) = self.level(level);
+        (min[index], max[index])
+    }
+    /* Combined planes of samples from..to, using the largest aligned
+    blocks that fit. */
+    pub fn range(&self, from: usize, to: usize) -> (M, M) {
+        let to = to.min(self.capacity());
+        let mut acc = (M::plane_zero(), M::plane_zero());
+        let mut i = from;
+        while i < to {
+            let mut level = 0;
+            while level < self.nb_levels
+                && i.is_multiple_of(2 << level)
+                && i + (2 << level) <= to {
+                level += 1;
+            }
+            let (min, max) = self.get(level, i >> level);
+            acc = (acc.0.plane_or(&min), acc.1.plane_or(&max));
+            i += 1 << level;
+        }
+        acc
+    }
+}
+
+fn mapped_planes<M>(map: &[u8]) -> (&[M], &[M]) {
+    let n = (map.len() - HEADER) / (2 * mem::size_of::<M>());
+    let p = unsafe { map.as_ptr().add(HEADER) } as *const M;
+    unsafe { (slice::from_raw_parts(p, n), slice::from_raw_parts(p.add(n), n)) }
+}
+
+/* Summary of a single channel. */
+pub fn value<M>(planes: (M, M), c: usize) -> Value where M: MipMap {
+    match (planes.1.plane_bit(c), planes.0.plane_bit(c)) {
+        (false, false) => Value::Neither,
+        (false, true)  => Value::Low,
+        (true,  false) => Value::High,
+        (true,  true)  => Value::Both,
+    }
+}
+
+/* Shared store for live captures.  The writer appends blocks while
+readers look at the store through read().  The guard holds a read
+lock, so all levels correspond to the same length, but it also blocks
+append().  Hold it only to query or copy out what is needed, not
+while e.g. drawing or waiting for input. */
+pub struct Live<M> {
+    store: Arc<RwLock<Store<M>>>,
+}
+impl<M> Clone for Live<M> {
+    fn clone(&self) -> Self {
+        Live { store: self.store.clone() }
+    }
+}
+impl<M> Live<M> where M: MipMap {
+    pub fn new(store: Store<M>) -> Live<M> {
+        Live { store: Arc::new(RwLock::new(store)) }
+    }
+    pub fn append(&self, samples: &[M]) -> usize {
+        self.store.write().unwrap().append(samples)
+    }
+    pub fn read(&self) -> RwLockReadGuard<'_, Store<M>> {
+        self.store.read().unwrap()
+    }
+}
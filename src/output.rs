@@ -0,0 +1,215 @@
+/* output: Formatting of decoder output.
+
+Decoder items are written in one of these formats:
+
+- native: the traditional per-decoder output.  Bytes are written raw,
+  words as hex lines, packets as "(len) - xx xx ..".
+- raw:    payload bytes only.  Words are little endian.
+- hex:    one item per line, packet bytes separated by spaces.
+- csv:    sample,time,decoder,type,payload with a header line.
+- jsonl:  one JSON object per line, with decoder, type, sample,
+  timestamp and payload fields.
+- pcap:   PCAPNG, one packet per item, see pcap.rs.  Bytes and words
+  become short packets with the raw format's payload.
+- erl:    {packet,4} frames for an Erlang port, with a tag byte per
+  message type.  Each item is preceded by a time message.
+- etf:    same, with Erlang external term format payload.  See erl.rs
+
+Only the erl formats carry error and status messages.
+
+Time stamps are sample indices in the capture, see Push::stamp.  Time
+in seconds is derived from the sample rate.
+
+*/
+
+use decoder::Item;
+use pcap;
+use erl::{self,Msg};
+use std::io::{self,Write};
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Format {
+    Native,
+    Raw,
+    Hex,
+    Csv,
+    Jsonl,
+    Pcap(u16),  // link type
+    Erl(bool),  // external term format
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "native" => Some(Format::Native),
+            "raw"    => Some(Format::Raw),
+            "hex"    => Some(Format::Hex),
+            "csv"    => Some(Format::Csv),
+            "jsonl"  => Some(Format::Jsonl),
+            "pcap"   => Some(Format::Pcap(pcap::LINKTYPE_RAW)),
+            "erl"    => Some(Format::Erl(false)),
+            "etf"    => Some(Format::Erl(true)),
+            _ => None,
+        }
+    }
+}
+
+pub struct Config {
+    pub format: Format,
+    pub decoder: String,
+    pub samplerate: usize,
+    pub word_bytes: usize,  // for raw and hex formatting of words
+}
+
+pub struct Writer<W: Write> {
+    config: Config,
+    out: W,
+    header: bool,
+    time: Option<usize>,  // last time message
+}
+
+// The pcap header is written here, so a run without items still
+// produces a valid file.
+pub fn init<W: Write>(config: Config, mut out: W) -> io::Result<Writer<W>> {
+    if let Format::Pcap(linktype) = config.format {
+        pcap::header(&mut out, linktype)?;
+        out.flush()?;
+    }
+    Ok(Writer { config, out, header: false, time: None })
+}
+
+fn type_name(item: &Item) -> &'static str {
+    match item {
+        Item::Byte(_)   => "byte",
+        Item::Word(_)   => "word",
+        Item::Packet(_) => "packet",
+    }
+}
+
+impl<W: Write> Writer<W> {
+    // Item produced at sample t.  Output is flushed per item, so it
+    // can be consumed while a capture is running.
+    pub fn item(&mut self, t: usize, item: &Item) -> io::Result<()> {
+        self.write(t, item)?;
+        self.out.flush()
+    }
+
+    // Name of the decoder for the following items, for a writer shared
+    // by several decoders.
+    pub fn set_decoder(&mut self, decoder: &str) {
+        if self.config.decoder != decoder { self.config.decoder = decoder.to_string(); }
+    }
+
+    pub fn error(&mut self, error: &str) -> io::Result<()> {
+        self.message(&Msg::Error(error))
+    }
+    pub fn status(&mut self, status: &str) -> io::Result<()> {
+        self.message(&Msg::Status(status))
+    }
+
+    fn message(&mut self, msg: &Msg) -> io::Result<()> {
+        if let Format::Erl(etf) = self.config.format {
+            let payload = if etf { erl::etf(msg) } else { erl::tagged(msg) };
+            erl::frame(&mut self.out, &payload)?;
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, t: usize, item: &Item) -> io::Result<()> {
+        let digits = 2 * self.config.word_bytes.max(1);
+        match self.config.format {
+            Format::Native => match item {
+                Item::Byte(b)   => self.out.write_all(&[*b]),
+                Item::Word(w)   => writeln!(self.out, "{:01$x}", w, 2),
+                Item::Packet(p) => {
+                    write!(self.out, "({}) -", p.len())?;
+                    for b in p { write!(self.out, " {:02x}", b)?; }
+                    writeln!(self.out)
+                }
+            },
+            Format::Raw => match item {
+                Item::Byte(b)   => self.out.write_all(&[*b]),
+                Item::Word(w)   => {
+                    let n = self.config.word_bytes.clamp(1, 8);
+                    self.out.write_all(&(*w as u64).to_le_bytes()[..n])
+                },
+                Item::Packet(p) => self.out.write_all(p),
+            },
+            Format::Hex => match item {
+                Item::Byte(b)   => writeln!(self.out, "{:02x}", b),
+                Item::Word(w)   => writeln!(self.out, "{:01$x}", w, digits),
+                Item::Packet(p) => {
+                    let hex: Vec<String> = p.iter().map(|b| format!("{:02x}", b)).collect();
+                    writeln!(self.out, "{}", hex.join(" "))
+                }
+            },
+            Format::Csv => {
+                if !self.header {
+                    self.header = true;
+                    writeln!(self.out, "sample,time,decoder,type,payload")?;
+                }
+                let payload = match item {
+                    Item::Byte(b)   => format!("{:02x}", b),
+                    Item::Word(w)   => format!("{:01$x}", w, digits),
+                    Item::Packet(p) => p.iter().map(|b| format!("{:02x}", b)).collect(),
+                };
+                writeln!(self.out, "{},{:.9},{},{},{}",
+                         t, self.time(t), self.config.decoder, type_name(item), payload)
+            },
+            Format::Jsonl => {
+                let payload = match item {
+                    Item::Byte(b)   => format!("{}", b),
+                    Item::Word(w)   => format!("{}", w),
+                    Item::Packet(p) => {
+                        let bytes: Vec<String> = p.iter().map(|b| b.to_string()).collect();
+                        format!("[{}]", bytes.join(","))
+                    }
+                };
+                writeln!(self.out,
+                         "{{\"decoder\":\"{}\",\"type\":\"{}\",\"sample\":{},\"timestamp\":{:.9},\"payload\":{}}}",
+                         json_escape(&self.config.decoder), type_name(item),
+                         t, self.time(t), payload)
+            },
+            Format::Pcap(_) => {
+                let ns = pcap::ns(t, self.config.samplerate);
+                match item {
+                    Item::Byte(b)   => pcap::packet(&mut self.out, ns, &[*b]),
+                    Item::Word(w)   => {
+                        let n = self.config.word_bytes.clamp(1, 8);
+                        pcap::packet(&mut self.out, ns, &(*w as u64).to_le_bytes()[..n])
+                    },
+                    Item::Packet(p) => pcap::packet(&mut self.out, ns, p),
+                }
+            },
+            Format::Erl(_) => {
+                if self.time != Some(t) {
+                    self.time = Some(t);
+                    self.message(&Msg::Time(t))?;
+                }
+                match item {
+                    Item::Byte(b)   => self.message(&Msg::Word(*b as usize, 1)),
+                    Item::Word(w)   => self.message(&Msg::Word(*w, self.config.word_bytes)),
+                    Item::Packet(p) => self.message(&Msg::Packet(p)),
+                }
+            },
+        }
+    }
+
+    fn time(&self, t: usize) -> f64 {
+        t as f64 / self.config.samplerate.max(1) as f64
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut rv = String::new();
+    for c in s.chars() {
+        match c {
+            '"'  => rv.push_str("\\\""),
+            '\\' => rv.push_str("\\\\"),
+            c if (c as u32) < 0x20 => rv.push_str(&format!("\\u{:04x}", c as u32)),
+            c => rv.push(c),
+        }
+    }
+    rv
+}
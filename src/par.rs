@@ -0,0 +1,122 @@
+/* par: Parallel analysis of captures held in memory.
+
+The state machines in sm are strictly sequential.  For offline
+analysis of large capture files, two forms of parallelism are
+available:
+
+- fanout: independent analyzers each run on their own thread, reading
+  from the same shared capture buffer.
+
+- chunked: a single analyzer is instantiated once per chunk.  The
+  capture is split at resync points (see sm::Resync) such that
+  concatenating the outputs of all chunks in order gives the same
+  result as running the analyzer over the whole capture.
+
+Both use scoped threads, so the capture is borrowed, not copied.
+
+*/
+
+use sm::{Push,Resync,Bus,apply};
+use std::thread;
+
+// Run independent jobs on a shared capture, one thread per job.
+// Results are returned in the order of the jobs.  Use boxed closures
+// to combine jobs of different types.
+pub fn fanout<B,R,F>(capture: &[B], jobs: Vec<F>) -> Vec<R>
+    where B: Sync,
+          R: Send,
+          F: FnOnce(&[B]) -> R + Send
+{
+    thread::scope(|scope| {
+        let threads: Vec<_> = jobs.into_iter()
+            .map(|job| scope.spawn(move || job(capture)))
+            .collect();
+        threads.into_iter()
+            .map(|t| t.join().unwrap())
+            .collect()
+    })
+}
+
+// Compute chunk boundaries.  Nominal boundaries are spaced evenly,
+// then moved forward to the next resync point.  The result contains
+// start and end of the capture, and is strictly increasing.  A chunk
+// that has no resync point is merged with the next one.
+pub fn splits<B,SM>(sm: &SM, capture: &[B], nb_chunks: usize) -> Vec<usize>
+    where SM: Resync<B>
+{
+    let n = capture.len();
+    let nb_chunks = nb_chunks.max(1);
+    let mut rv = vec![0];
+    for i in 1..nb_chunks {
+        let nominal = (i * n) / nb_chunks;
+        let last = *rv.last().unwrap();
+        if nominal <= last { continue; }
+        match sm.resync(capture, nominal) {
+            Some(split) if split > last && split < n => rv.push(split),
+            _ => (),
+        }
+    }
+    rv.push(n);
+    rv
+}
+
+// Run an analyzer over a capture split into resynchronizing chunks.
+// `init` is called once per chunk to create a fresh machine.  Outputs
+// are stitched back together in capture order.
+// Samples are presented to the machine as usize, which allows use of
+// the dynamic decoders in the decoder module.
+pub fn chunked<B,O,SM,F>(capture: &[B], nb_chunks: usize, init: F) -> Vec<O>
+    where B:  Bus+Sync,
+          O:  Send,
+          SM: Push<usize,O>+Resync<B>,
+          F:  Fn() -> SM + Sync
+{
+    chunks(capture, nb_chunks, |_| init())
+}
+
+// Same, but outputs are paired with the index of the sample in the
+// capture that produced them, as with Push::stamp.
+pub fn stamped<B,O,SM,F>(capture: &[B], nb_chunks: usize, init: F) -> Vec<(usize,O)>
+    where B:  Bus+Sync,
+          O:  Send,
+          SM: Push<usize,O>+Resync<B>,
+          F:  Fn() -> SM + Sync
+{
+    chunks(capture, nb_chunks, |start| init().stamp().starting_at(start))
+}
+
+// `init` gets the start index of the chunk.
+fn chunks<B,O,SM,F>(capture: &[B], nb_chunks: usize, init: F) -> Vec<O>
+    where B:  Bus+Sync,
+          O:  Send,
+          SM: Push<usize,O>+Resync<B>,
+          F:  Fn(usize) -> SM + Sync
+{
+    let bounds = splits(&init(0), capture, nb_chunks);
+    let n = capture.len();
+    let jobs: Vec<_> = bounds.windows(2).map(|w| {
+        let (start, end) = (w[0], w[1]);
+        let init = &init;
+        move |capture: &[B]| {
+            let mut sm = init(start);
+            let ins = capture[start..end].iter().map(|b| b.as_usize());
+            // Only the end of the capture is flushed.  Chunk ends are
+            // resync points, where a sequential run continues.
+            if end == n {
+                apply(&mut sm, ins).collect::<Vec<O>>()
+            }
+            else {
+                ins.filter_map(|i| sm.push(i)).collect::<Vec<O>>()
+            }
+        }
+    }).collect();
+    fanout(capture, jobs).into_iter().flatten().collect()
+}
+
+// Number of threads to use if not specified.
+pub fn nb_threads() -> usize {
+    match thread::available_parallelism() {
+        Ok(n) => n.get(),
+        Err(_) => 1,
+    }
+}
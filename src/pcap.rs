@@ -0,0 +1,83 @@
+/* pcap: PCAPNG export of decoded packets, e.g. for Wireshark.
+
+The file contains a Section Header Block, a single Interface
+Description Block with the link type, and one Enhanced Packet Block
+per packet.  Timestamps are in nanoseconds since the start of the
+capture, computed from the sample index and sample rate.
+
+*/
+
+use std::io::{self,Write};
+
+// Link types, see https://www.tcpdump.org/linktypes.html
+pub const LINKTYPE_RAW:   u16 = 101;  // raw IPv4 or IPv6, e.g. SLIP payload
+pub const LINKTYPE_IPV4:  u16 = 228;
+pub const LINKTYPE_IPV6:  u16 = 229;
+pub const LINKTYPE_USER0: u16 = 147;  // USER0-USER15 for custom protocols
+
+// Accepts raw, ipv4, ipv6, user0 to user15, or a number.
+pub fn parse_linktype(name: &str) -> Option<u16> {
+    match name {
+        "raw"  => Some(LINKTYPE_RAW),
+        "ipv4" => Some(LINKTYPE_IPV4),
+        "ipv6" => Some(LINKTYPE_IPV6),
+        _ => match name.strip_prefix("user") {
+            Some(n) => match n.parse::<u16>() {
+                Ok(n) if n < 16 => Some(LINKTYPE_USER0 + n),
+                _ => None,
+            },
+            None => name.parse().ok(),
+        }
+    }
+}
+
+// Blocks are written as a whole, with the total length at both ends.
+fn block<W: Write>(out: &mut W, typ: u32, body: &[u8]) -> io::Result<()> {
+    let len = (12 + body.len()) as u32;
+    out.write_all(&typ.to_le_bytes())?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&len.to_le_bytes())
+}
+
+fn pad(body: &mut Vec<u8>) {
+    while !body.len().is_multiple_of(4) { body.push(0); }
+}
+
+// Section header and interface description.
+pub fn header<W: Write>(out: &mut W, linktype: u16) -> io::Result<()> {
+    let mut shb = vec![];
+    shb.extend_from_slice(&0x1A2B3C4Du32.to_le_bytes()); // byte order magic
+    shb.extend_from_slice(&1u16.to_le_bytes());          // major
+    shb.extend_from_slice(&0u16.to_le_bytes());          // minor
+    shb.extend_from_slice(&(-1i64).to_le_bytes());       // section length unknown
+    block(out, 0x0A0D0D0A, &shb)?;
+
+    let mut idb = vec![];
+    idb.extend_from_slice(&linktype.to_le_bytes());
+    idb.extend_from_slice(&0u16.to_le_bytes());          // reserved
+    idb.extend_from_slice(&0u32.to_le_bytes());          // snaplen: no limit
+    idb.extend_from_slice(&9u16.to_le_bytes());          // if_tsresol
+    idb.extend_from_slice(&1u16.to_le_bytes());
+    idb.extend_from_slice(&[9, 0, 0, 0]);                // 10^-9 s
+    idb.extend_from_slice(&[0, 0, 0, 0]);                // opt_endofopt
+    block(out, 1, &idb)
+}
+
+// Enhanced packet block on interface 0, timestamp in nanoseconds.
+pub fn packet<W: Write>(out: &mut W, ns: u64, data: &[u8]) -> io::Result<()> {
+    let mut epb = vec![];
+    epb.extend_from_slice(&0u32.to_le_bytes());
+    epb.extend_from_slice(&((ns >> 32) as u32).to_le_bytes());
+    epb.extend_from_slice(&(ns as u32).to_le_bytes());
+    epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    epb.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    epb.extend_from_slice(data);
+    pad(&mut epb);
+    block(out, 6, &epb)
+}
+
+// Sample index to nanoseconds.
+pub fn ns(t: usize, samplerate: usize) -> u64 {
+    (t as u128 * 1_000_000_000 / samplerate.max(1) as u128) as u64
+}
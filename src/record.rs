@@ -0,0 +1,214 @@
+/* record: Save windows around trigger events to files.
+
+The recorder takes the output of a trigger::Gate, i.e. samples with
+their index in the input stream, and writes each segment of
+consecutive samples to its own file.  The ring buffer with samples
+before the trigger is kept by the gate.  Files are named
+
+  <prefix>-<sequence>-<first sample>.<ext>
+
+and are written under a temporary name first, so a file with the
+final name is always complete.
+
+Old files are removed to stay within a maximum number of files and a
+maximum total size.  Files from a previous run with the same prefix
+count as well, so a recorder that is restarted continues where it
+left off.  The newest file is always kept.
+
+*/
+
+use sigrok;
+use vcd;
+use std::collections::VecDeque;
+use std::fs::{self,File};
+use std::io::{self,BufWriter};
+use std::path::{Path,PathBuf};
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Format {
+    Raw,     // one byte per sample, as read by file=
+    Vcd,
+    Sigrok,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "raw" | "bin" => Some(Format::Raw),
+            "vcd" => Some(Format::Vcd),
+            "sr" | "sigrok" => Some(Format::Sigrok),
+            _ => None,
+        }
+    }
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            Format::Raw => "bin",
+            Format::Vcd => "vcd",
+            Format::Sigrok => "sr",
+        }
+    }
+}
+
+// Size with an optional k, M or G suffix, in bytes.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let (digits, mul) = match s.chars().last() {
+        Some('k') | Some('K') => (&s[..s.len()-1], 1 << 10),
+        Some('M') => (&s[..s.len()-1], 1 << 20),
+        Some('G') => (&s[..s.len()-1], 1 << 30),
+        _ => (s, 1),
+    };
+    digits.parse::<u64>().ok().and_then(|n| n.checked_mul(mul))
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub prefix: String,      // directory and start of the file name
+    pub format: Format,
+    pub samplerate: usize,
+    pub names: Vec<String>,  // channel names
+    pub max_samples: usize,  // per file, 0 for no limit
+    pub max_files: usize,    // 0 for no limit
+    pub quota: u64,          // total bytes, 0 for no limit
+}
+
+pub struct Recorder {
+    pub config: Config,
+    dir: PathBuf,
+    name: String,
+    files: VecDeque<(PathBuf, u64)>,  // oldest first, with size
+    sequence: usize,
+    start: usize,         // index of the first sample in samples
+    samples: Vec<usize>,
+}
+
+// Sequence number of a file written with this prefix.
+fn sequence(name: &str, file: &str) -> Option<usize> {
+    let rest = file.strip_prefix(name)?.strip_prefix('-')?;
+    let mut fields = rest.split(['-', '.']);
+    let seq = fields.next()?.parse().ok()?;
+    fields.next()?.parse::<usize>().ok()?;
+    Some(seq)
+}
+
+pub fn init(config: Config) -> io::Result<Recorder> {
+    // Fail before the first trigger rather than when writing.
+    match config.format {
+        Format::Raw if config.names.len() > 8 =>
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("record: raw format has 8 channels, not {}", config.names.len()))),
+        Format::Vcd if config.names.len() > usize::BITS as usize =>
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("record: {} channels, at most {}", config.names.len(), usize::BITS))),
+        Format::Sigrok => { sigrok::Session::new(config.samplerate, config.names.clone())?; },
+        _ => (),
+    }
+    let prefix = Path::new(&config.prefix);
+    let dir = match prefix.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = match prefix.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "record: no file name")),
+    };
+    fs::create_dir_all(&dir)?;
+    let mut found = vec![];
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let file = entry.file_name().to_string_lossy().to_string();
+        if !file.ends_with(config.format.extension()) { continue; }
+        if let Some(seq) = sequence(&name, &file) {
+            found.push((seq, entry.path(), entry.metadata()?.len()));
+        }
+    }
+    found.sort();
+    let sequence = found.last().map(|f| f.0 + 1).unwrap_or(0);
+    let files = found.into_iter().map(|(_, path, size)| (path, size)).collect();
+    let mut recorder = Recorder {
+        config, dir, name, files, sequence,
+        start: 0,
+        samples: vec![],
+    };
+    recorder.expire()?;
+    Ok(recorder)
+}
+
+impl Recorder {
+    // Sample t of the input stream.  A gap in t, or a full file, ends
+    // the current file.  Returns the name of a completed file.
+    pub fn push(&mut self, t: usize, bus: usize) -> io::Result<Option<PathBuf>> {
+        let mut rv = None;
+        if !self.samples.is_empty() {
+            let full = self.config.max_samples > 0 && self.samples.len() >= self.config.max_samples;
+            if full || t != self.start + self.samples.len() {
+                rv = Some(self.write()?);
+            }
+        }
+        if self.samples.is_empty() { self.start = t; }
+        self.samples.push(bus);
+        Ok(rv)
+    }
+    // Write out the current file, e.g. at end of input.
+    pub fn flush(&mut self) -> io::Result<Option<PathBuf>> {
+        if self.samples.is_empty() { return Ok(None); }
+        self.write().map(Some)
+    }
+    // Files on disk, oldest first.
+    pub fn files(&self) -> Vec<&Path> {
+        self.files.iter().map(|f| f.0.as_path()).collect()
+    }
+    // Total size of the files on disk.
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|f| f.1).sum()
+    }
+
+    fn write(&mut self) -> io::Result<PathBuf> {
+        let c = &self.config;
+        let file = format!("{}-{:06}-{}.{}", self.name, self.sequence, self.start,
+                           c.format.extension());
+        let path = self.dir.join(&file);
+        let tmp = self.dir.join(format!(".{}.tmp", file));
+        match c.format {
+            Format::Raw => {
+                let bytes: Vec<u8> = self.samples.iter().map(|&b| b as u8).collect();
+                fs::write(&tmp, bytes)?;
+            },
+            Format::Vcd => {
+                let mut vcd = vcd::Writer::new(BufWriter::new(File::create(&tmp)?),
+                                               c.samplerate, &c.names);
+                for (i, &bus) in self.samples.iter().enumerate() {
+                    vcd.sample((self.start + i) as u64, bus)?;
+                }
+                vcd.finish((self.start + self.samples.len()) as u64)?;
+            },
+            Format::Sigrok => {
+                let mut session = sigrok::Session::new(c.samplerate, c.names.clone())?;
+                for &bus in self.samples.iter() { session.push(bus); }
+                sigrok::write(&tmp.to_string_lossy(), &session)?;
+            },
+        }
+        fs::rename(&tmp, &path)?;
+        let size = fs::metadata(&path)?.len();
+        self.files.push_back((path.clone(), size));
+        self.sequence += 1;
+        self.samples.clear();
+        self.expire()?;
+        Ok(path)
+    }
+
+    // Remove the oldest files until within limits.
+    fn expire(&mut self) -> io::Result<()> {
+        let c = &self.config;
+        while self.files.len() > 1 {
+            let too_many = c.max_files > 0 && self.files.len() > c.max_files;
+            let too_large = c.quota > 0 && self.files.iter().map(|f| f.1).sum::<u64>() > c.quota;
+            if !too_many && !too_large { break; }
+            if let Some((path, _)) = self.files.pop_front() {
+                if let Err(e) = fs::remove_file(&path) {
+                    if e.kind() != io::ErrorKind::NotFound { return Err(e); }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+/* render: Static waveform images, e.g. for bug reports.
+
+A time range of a capture is laid out once as a list of rectangles
+and text, which is then written as SVG or rasterized to a grayscale
+PNG.  As in the terminal viewer, each pixel column is summarized by
+the mipmap, so the cost depends on the image width and not on the
+length of the range.  Columns where a channel toggles are hatched.
+
+  +------+---------------------------------+
+  |      | time axis                       |
+  | d0   | waveform                        |
+  | ..   |                                 |
+  | uart | annotations                     |
+  +------+---------------------------------+
+
+The PNG has a built-in 3x5 font, drawn at twice the size.  Lower
+case is drawn as upper case.
+
+*/
+
+use mipmap::{self,MipMap,Store,Value};
+use view::Annotation;
+use flate2::Crc;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::io::{self,Write};
+
+pub struct Config {
+    pub from: usize,        // first sample
+    pub to: usize,          // end sample
+    pub width: usize,       // pixels for the waveforms
+    pub samplerate: usize,  // time axis in seconds, samples if 0
+}
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Fill {
+    Solid(u8),  // gray level
+    Hatch,
+}
+
+#[derive(Clone,PartialEq,Debug)]
+pub enum Shape {
+    Rect { x: usize, y: usize, w: usize, h: usize, fill: Fill },
+    Text { x: usize, y: usize, text: String },  // top left
+}
+
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub shapes: Vec<Shape>,
+}
+
+pub const ROW: usize = 20;        // height of a channel or annotation row
+pub const CHAR: usize = 8;        // text advance
+const TEXT: usize = 10;           // text height
+const HIGH: usize = 4;            // waveform levels within a row
+const LOW: usize = 15;
+const AXIS: u8 = 96;
+const MARK: u8 = 160;
+
+fn rect(x: usize, y: usize, w: usize, h: usize, fill: Fill) -> Shape {
+    Shape::Rect { x, y, w, h, fill }
+}
+fn text(x: usize, y: usize, text: &str) -> Shape {
+    Shape::Text { x, y, text: text.to_string() }
+}
+
+// 1, 2 or 5 times a power of 10, at least x.
+fn nice(x: f64) -> f64 {
+    let p = 10f64.powf(x.log10().floor());
+    for m in [1.0, 2.0, 5.0] {
+        if m * p >= x { return m * p; }
+    }
+    10.0 * p
+}
+
+// Tick marks for roughly one label per 100 pixels: sample index and
+// label text.
+pub fn ticks(c: &Config) -> Vec<(usize, String)> {
+    let n = c.to.saturating_sub(c.from);
+    if n == 0 || c.width == 0 { return vec![]; }
+    let per_tick = (n as f64 * 100.0 / c.width as f64).max(1.0);
+    let mut rv = vec![];
+    if c.samplerate == 0 {
+        let step = nice(per_tick) as usize;
+        let mut t = c.from.div_ceil(step) * step;
+        while t < c.to {
+            rv.push((t, t.to_string()));
+            t += step;
+        }
+    }
+    else {
+        let sr = c.samplerate as f64;
+        let step = nice(per_tick / sr);
+        // Largest unit not above the step, so labels are integers.
+        let (unit, name) = [(1.0, "s"), (1e-3, "ms"), (1e-6, "us"), (1e-9, "ns")]
+            .iter().cloned().find(|&(u, _)| u <= step * 1.000001).unwrap_or((1e-9, "ns"));
+        let mut k = (c.from as f64 / sr / step).ceil() as usize;
+        loop {
+            let t = (k as f64 * step * sr).round() as usize;
+            if t >= c.to { break; }
+            if t >= c.from {
+                rv.push((t, format!("{}{}", (k as f64 * step / unit).round() as u64, name)));
+            }
+            k += 1;
+        }
+    }
+    rv
+}
+
+// Layout of a time range: axis, one row per channel and one per
+// annotation.
+pub fn layout<M: MipMap>(store: &Store<M>, names: &[String],
+                         annotations: &[Annotation], c: &Config) -> Image {
+    let label = names.iter().map(|n| n.len())
+        .chain(annotations.iter().map(|a| a.name.len()))
+        .max().unwrap_or(0) * CHAR + CHAR;
+    let n = c.to.saturating_sub(c.from);
+    let x = |t: usize| label + ((t - c.from) as f64 * c.width as f64 / n.max(1) as f64) as usize;
+    let width = label + c.width;
+    let height = ROW * (1 + names.len() + annotations.len());
+    let mut shapes = vec![];
+
+    shapes.push(rect(label, ROW - 1, c.width, 1, Fill::Solid(AXIS)));
+    for (t, l) in ticks(c) {
+        shapes.push(rect(x(t), ROW - 5, 1, 4, Fill::Solid(AXIS)));
+        shapes.push(text(x(t) + 2, 2, &l));
+    }
+
+    // Each column covers at least one sample.
+    let columns: Vec<(M, M)> = (0..c.width).map(|i| {
+        let from = c.from + i * n / c.width;
+        let to = (c.from + (i + 1) * n / c.width).max(from + 1);
+        store.range(from, to.min(c.to))
+    }).collect();
+
+    for (ch, name) in names.iter().enumerate() {
+        let y = ROW * (1 + ch);
+        shapes.push(text(0, y + 5, name));
+        let values: Vec<Value> = columns.iter().map(|&p| mipmap::value(p, ch)).collect();
+        // Runs of equal columns become one shape.
+        let mut i = 0;
+        while i < values.len() {
+            let v = values[i];
+            let mut j = i;
+            while j < values.len() && values[j] == v { j += 1; }
+            let x0 = label + i;
+            match v {
+                Value::Low  => shapes.push(rect(x0, y + LOW, j - i, 1, Fill::Solid(0))),
+                Value::High => shapes.push(rect(x0, y + HIGH, j - i, 1, Fill::Solid(0))),
+                Value::Both => shapes.push(rect(x0, y + HIGH, j - i, LOW - HIGH + 1, Fill::Hatch)),
+                Value::Neither => (),
+            }
+            if i > 0 {
+                match (values[i - 1], v) {
+                    (Value::Low, Value::High) | (Value::High, Value::Low) =>
+                        shapes.push(rect(x0, y + HIGH, 1, LOW - HIGH + 1, Fill::Solid(0))),
+                    _ => (),
+                }
+            }
+            i = j;
+        }
+    }
+
+    for (r, a) in annotations.iter().enumerate() {
+        let y = ROW * (1 + names.len() + r);
+        shapes.push(text(0, y + 5, &a.name));
+        let mut free = 0;  // first pixel not yet written
+        for (t, l) in a.items.iter() {
+            if *t < c.from || *t >= c.to { continue; }
+            let x0 = x(*t);
+            if x0 < free { continue; }
+            let room = width.saturating_sub(x0 + 2) / CHAR;
+            let l: String = l.chars().take(room).collect();
+            shapes.push(rect(x0, y + 2, 1, ROW - 4, Fill::Solid(MARK)));
+            if !l.is_empty() { shapes.push(text(x0 + 2, y + 5, &l)); }
+            free = x0 + 2 + l.chars().count() * CHAR + CHAR / 2;
+        }
+    }
+    Image { width, height, shapes }
+}
+
+fn escape(text: &str) -> String {
+    text.chars().map(|c| match c {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        '"' => "&quot;".to_string(),
+        c => c.to_string(),
+    }).collect()
+}
+
+pub fn svg<W: Write>(out: &mut W, image: &Image) -> io::Result<()> {
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+             image.width, image.height)?;
+    writeln!(out, "<defs><pattern id=\"hatch\" width=\"4\" height=\"4\" \
+                   patternUnits=\"userSpaceOnUse\" patternTransform=\"rotate(45)\">\
+                   <rect width=\"2\" height=\"4\" fill=\"#808080\"/></pattern></defs>")?;
+    writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"#ffffff\"/>")?;
+    writeln!(out, "<g font-family=\"monospace\" font-size=\"12\">")?;
+    for shape in image.shapes.iter() {
+        match *shape {
+            Shape::Rect { x, y, w, h, fill } => {
+                let fill = match fill {
+                    Fill::Solid(g) => format!("#{:02x}{:02x}{:02x}", g, g, g),
+                    Fill::Hatch => "url(#hatch)".to_string(),
+                };
+                writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>",
+                         x, y, w, h, fill)?;
+            },
+            Shape::Text { x, y, ref text } => {
+                writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text>", x, y + TEXT, escape(text))?;
+            },
+        }
+    }
+    writeln!(out, "</g>\n</svg>")
+}
+
+// Rows of 3 pixels, most significant bit left.
+const FONT: &[(char, [u8; 5])] = &[
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('=', [0b000, 0b111, 0b000, 0b111, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+// Unknown characters are drawn as a box.
+fn glyph(c: char) -> [u8; 5] {
+    let c = c.to_ascii_uppercase();
+    match FONT.iter().find(|&&(f, _)| f == c) {
+        Some(&(_, rows)) => rows,
+        None => [0b111, 0b101, 0b101, 0b101, 0b111],
+    }
+}
+
+// Grayscale pixels, row by row.
+pub fn raster(image: &Image) -> Vec<u8> {
+    let (width, height) = (image.width, image.height);
+    let mut pixels = vec![255u8; width * height];
+    {
+        let mut set = |x: usize, y: usize, g: u8| {
+            if x < width && y < height { pixels[y * width + x] = g; }
+        };
+        for shape in image.shapes.iter() {
+            match *shape {
+                Shape::Rect { x, y, w, h, fill } => {
+                    for py in y..y+h {
+                        for px in x..x+w {
+                            match fill {
+                                Fill::Solid(g) => set(px, py, g),
+                                Fill::Hatch if (px + py) % 4 < 2 => set(px, py, 128),
+                                Fill::Hatch => (),
+                            }
+                        }
+                    }
+                },
+                Shape::Text { x, y, ref text } => {
+                    for (i, c) in text.chars().enumerate() {
+                        for (row, bits) in glyph(c).iter().enumerate() {
+                            for col in 0..3 {
+                                if bits & (4 >> col) == 0 { continue; }
+                                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                                    set(x + i * CHAR + 2 * col + dx, y + 2 * row + dy, 0);
+                                }
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+    pixels
+}
+
+fn chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc.sum().to_be_bytes())
+}
+
+// 8 bit grayscale PNG, without row filters.
+pub fn png<W: Write>(out: &mut W, image: &Image) -> io::Result<()> {
+    if image.width == 0 || image.height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("png: empty image {}x{}", image.width, image.height)));
+    }
+    let pixels = raster(image);
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut ihdr = vec![];
+    ihdr.extend_from_slice(&(image.width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(image.height as u32).to_be_bytes());
+    ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);  // depth, gray, deflate, filter, no interlace
+    chunk(out, b"IHDR", &ihdr)?;
+    let mut z = ZlibEncoder::new(vec![], Compression::default());
+    for row in pixels.chunks(image.width.max(1)) {
+        z.write_all(&[0])?;
+        z.write_all(row)?;
+    }
+    chunk(out, b"IDAT", &z.finish()?)?;
+    chunk(out, b"IEND", &[])
+}
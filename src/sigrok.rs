@@ -0,0 +1,198 @@
+/* sigrok: Session files (.sr) as used by sigrok-cli and PulseView.
+
+A session file is a zip archive containing:
+
+- version: "2"
+- metadata: INI file with samplerate, probe names and unit size
+- logic-1-1, logic-1-2, ...: raw sample chunks, unitsize bytes per
+  sample, little endian.  Older files use a single logic-1 file.
+
+Only the logic data of the first device is supported.  Analog probes
+are ignored on import.
+
+*/
+
+use std::fs::File;
+use std::io::{self,Read,Write,Seek};
+use zip::{ZipArchive,ZipWriter};
+use zip::write::FileOptions;
+
+// Size of data chunks written to the archive.
+const CHUNK: usize = 4 << 20;
+
+pub struct Session {
+    pub samplerate: usize,
+    pub probes: Vec<String>,   // one name per channel
+    pub unitsize: usize,       // bytes per sample
+    pub data: Vec<u8>,
+}
+
+impl Session {
+    // Unit size is the smallest that fits all probes.  Samples are bus
+    // values, so there are at most usize::BITS probes.
+    pub fn new(samplerate: usize, probes: Vec<String>) -> io::Result<Session> {
+        if probes.len() > usize::BITS as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("sigrok: {} probes, at most {}", probes.len(), usize::BITS)));
+        }
+        let unitsize = probes.len().max(1).div_ceil(8);
+        Ok(Session { samplerate, probes, unitsize, data: vec![] })
+    }
+    pub fn push(&mut self, bus: usize) {
+        let bytes = bus.to_le_bytes();
+        self.data.extend_from_slice(&bytes[..self.unitsize]);
+    }
+    pub fn len(&self) -> usize {
+        self.data.len() / self.unitsize
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // Samples as bus values.
+    pub fn samples(&self) -> impl Iterator<Item=usize> + '_ {
+        self.data.chunks_exact(self.unitsize).map(|unit| {
+            unit.iter().rev().fold(0, |acc, &b| (acc << 8) | b as usize)
+        })
+    }
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Sample rate as written by sigrok, e.g. "1 MHz", "500 kHz", or a
+// plain number in Hz.
+pub fn parse_samplerate(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: f64 = n.parse().ok()?;
+    let scale = match unit.trim() {
+        "" | "Hz" => 1.0,
+        "kHz" => 1e3,
+        "MHz" => 1e6,
+        "GHz" => 1e9,
+        _ => return None,
+    };
+    Some((n * scale).round() as usize)
+}
+
+pub fn format_samplerate(sr: usize) -> String {
+    for &(scale, unit) in [(1_000_000_000, "GHz"), (1_000_000, "MHz"), (1_000, "kHz")].iter() {
+        if sr >= scale && sr.is_multiple_of(scale) {
+            return format!("{} {}", sr / scale, unit);
+        }
+    }
+    format!("{} Hz", sr)
+}
+
+pub fn read_from<R: Read+Seek>(input: R) -> io::Result<Session> {
+    let mut zip = ZipArchive::new(input)?;
+    let mut metadata = String::new();
+    zip.by_name("metadata")?.read_to_string(&mut metadata)?;
+
+    let mut samplerate = None;
+    let mut capturefile = "logic-1".to_string();
+    let mut unitsize = 1;
+    let mut probes: Vec<(usize, String)> = vec![];
+    let mut section = String::new();
+    let mut device = false;
+    for line in metadata.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            // Only the first device is used.
+            if device && line != section { break; }
+            section = line.to_string();
+            device = line.starts_with("[device");
+            continue;
+        }
+        if !device { continue; }
+        let (key, value) = match line.find('=') {
+            Some(i) => (line[..i].trim(), line[i+1..].trim()),
+            None => continue,
+        };
+        match key {
+            "samplerate" => samplerate = parse_samplerate(value),
+            "capturefile" => capturefile = value.to_string(),
+            "unitsize" => unitsize = match value.parse() {
+                Ok(n) if (1..=8).contains(&n) => n,
+                _ => return Err(invalid(format!("sigrok: bad unitsize {}", value))),
+            },
+            _ => {
+                if let Some(n) = key.strip_prefix("probe") {
+                    // Probes are numbered from 1.
+                    match n.parse::<usize>() {
+                        Ok(n) if (1..=usize::BITS as usize).contains(&n) => probes.push((n, value.to_string())),
+                        Ok(_) => return Err(invalid(format!("sigrok: bad probe {}", key))),
+                        Err(_) => (),
+                    }
+                }
+            }
+        }
+    }
+    let samplerate = match samplerate {
+        Some(sr) => sr,
+        None => return Err(invalid("sigrok: no samplerate".to_string())),
+    };
+    let nb_probes = probes.iter().map(|p| p.0).max().unwrap_or(0);
+    let mut names: Vec<String> = (1..=nb_probes).map(|n| format!("D{}", n - 1)).collect();
+    for (n, name) in probes {
+        names[n - 1] = name;
+    }
+
+    // Chunks are numbered from 1.  Without chunks, the data is in a
+    // single file.
+    let mut data = vec![];
+    let mut chunk = 1;
+    loop {
+        match zip.by_name(&format!("{}-{}", capturefile, chunk)) {
+            Ok(mut file) => { file.read_to_end(&mut data)?; }
+            Err(zip::result::ZipError::FileNotFound) => break,
+            Err(e) => return Err(e.into()),
+        }
+        chunk += 1;
+    }
+    if chunk == 1 {
+        zip.by_name(&capturefile)?.read_to_end(&mut data)?;
+    }
+    data.truncate(data.len() - data.len() % unitsize);
+    Ok(Session { samplerate, probes: names, unitsize, data })
+}
+
+pub fn write_to<W: Write+Seek>(output: W, session: &Session) -> io::Result<()> {
+    let mut zip = ZipWriter::new(output);
+    let options = FileOptions::default();
+
+    zip.start_file("version", options)?;
+    zip.write_all(b"2")?;
+
+    zip.start_file("metadata", options)?;
+    writeln!(zip, "[global]")?;
+    writeln!(zip, "sigrok version=0.5.2")?;
+    writeln!(zip)?;
+    writeln!(zip, "[device 1]")?;
+    writeln!(zip, "capturefile=logic-1")?;
+    writeln!(zip, "total probes={}", session.probes.len())?;
+    writeln!(zip, "samplerate={}", format_samplerate(session.samplerate))?;
+    writeln!(zip, "total analog=0")?;
+    for (i, name) in session.probes.iter().enumerate() {
+        writeln!(zip, "probe{}={}", i + 1, name)?;
+    }
+    writeln!(zip, "unitsize={}", session.unitsize)?;
+
+    let chunk = CHUNK - CHUNK % session.unitsize;
+    for (i, data) in session.data.chunks(chunk).enumerate() {
+        zip.start_file(format!("logic-1-{}", i + 1), options)?;
+        zip.write_all(data)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+pub fn read(path: &str) -> io::Result<Session> {
+    read_from(File::open(path)?)
+}
+
+pub fn write(path: &str, session: &Session) -> io::Result<()> {
+    write_to(File::create(path)?, session)
+}
@@ -1,9 +1,12 @@
 
 // sm: State Machines for logic analysis
 
+use std::marker::PhantomData;
+
 // ---- Apply ----
 
-// Apply a Push state machine to an iterator.
+// Apply a Push state machine to an iterator.  When the input is
+// exhausted, the machine is flushed to produce any trailing output.
 pub fn apply<'a,In,Out,SM,Ins>
     (sm: &'a mut SM, ins: Ins) -> impl 'a+Iterator<Item=Out>
     where In:  'a,
@@ -11,7 +14,30 @@ pub fn apply<'a,In,Out,SM,Ins>
           SM:  'a+Push<In,Out>,
           Ins: 'a+Iterator<Item=In>
 {
-    ins.filter_map(move |i| sm.push(i))
+    Apply { sm, ins, eof: false, phantom: PhantomData }
+}
+
+struct Apply<'a,SM:'a,Ins,In,Out> {
+    sm: &'a mut SM,
+    ins: Ins,
+    eof: bool,
+    phantom: PhantomData<fn(In) -> Out>,
+}
+impl<'a,In,Out,SM,Ins> Iterator for Apply<'a,SM,Ins,In,Out>
+    where SM:  Push<In,Out>,
+          Ins: Iterator<Item=In>
+{
+    type Item = Out;
+    #[inline(always)]
+    fn next(&mut self) -> Option<Out> {
+        if !self.eof {
+            for i in &mut self.ins {
+                if let Some(o) = self.sm.push(i) { return Some(o); }
+            }
+            self.eof = true;
+        }
+        self.sm.flush()
+    }
 }
 
 
@@ -20,11 +46,295 @@ pub fn apply<'a,In,Out,SM,Ins>
 // A state machine takes the next input item, and possibly produces a
 // higher level parsed result item.
 pub trait Push<I,O> {
-    fn push(&mut self, I) -> Option<O>;
+    fn push(&mut self, input: I) -> Option<O>;
+
+    // Return to the initial state.  Configuration is kept.
+    fn reset(&mut self);
+
+    // Produce output from partially accumulated state, e.g. at end of
+    // input.  Composite machines can have more than one item pending,
+    // so call this until it returns None.
+    fn flush(&mut self) -> Option<O>;
+
+    // Note that the machines below accept any Bus type as input, which
+    // makes calls to reset and flush ambiguous.  They are therefore
+    // also provided as inherent methods.
+
+    // Combinators.  These build a composite machine that is itself a
+    // Push, so a pipeline can be stored as a single value, boxed and
+    // passed around.  Compare to the corresponding Iterator methods.
+
+    // Feed outputs into another machine.
+    fn then<P,O2>(self, next: P) -> Then<Self,P,I,O>
+        where Self: Sized, P: Push<O,O2>
+    {
+        Then { first: self, next, phantom: PhantomData }
+    }
+    fn map<F,O2>(self, f: F) -> Map<Self,F,I,O>
+        where Self: Sized, F: FnMut(O) -> O2
+    {
+        Map { sm: self, f, phantom: PhantomData }
+    }
+    fn filter<F>(self, f: F) -> Filter<Self,F,I>
+        where Self: Sized, F: FnMut(&O) -> bool
+    {
+        Filter { sm: self, f, phantom: PhantomData }
+    }
+    fn inspect<F>(self, f: F) -> Inspect<Self,F,I>
+        where Self: Sized, F: FnMut(&O)
+    {
+        Inspect { sm: self, f, phantom: PhantomData }
+    }
+    // Pass outputs through, and also feed a copy into a side machine.
+    // Outputs of the side machine are dropped, so it is typically
+    // used for its side effects, e.g. terminated by inspect.
+    fn tee<P,X>(self, side: P) -> Tee<Self,P,I,X>
+        where Self: Sized, P: Push<O,X>, O: Clone
+    {
+        Tee { sm: self, side, phantom: PhantomData }
+    }
+    // Pair outputs with the index of the input sample that produced
+    // them.  Flushed outputs get the index just past the last input.
+    fn stamp(self) -> Stamp<Self,I>
+        where Self: Sized
+    {
+        Stamp { sm: self, start: 0, t: 0, phantom: PhantomData }
+    }
+}
+
+impl<I,O,P> Push<I,O> for Box<P> where P: ?Sized+Push<I,O> {
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> { (**self).push(input) }
+    fn reset(&mut self) { (**self).reset() }
+    fn flush(&mut self) -> Option<O> { (**self).flush() }
+}
+// Allows combinators to borrow a machine, e.g. to keep access to its
+// state after the composite is done.
+impl<I,O,P> Push<I,O> for &mut P where P: ?Sized+Push<I,O> {
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> { (**self).push(input) }
+    fn reset(&mut self) { (**self).reset() }
+    fn flush(&mut self) -> Option<O> { (**self).flush() }
+}
+
+// Combinator state.  The PhantomData fields name the input type and
+// the intermediate type, which are otherwise not constrained.  Most
+// machines implement Push for any Bus input, so without this the
+// input type of e.g. uart.then(slip) could not be inferred.
+pub struct Then<A,B,I,M>  { first: A, next: B, phantom: PhantomData<fn(I) -> M> }
+pub struct Map<A,F,I,M>   { sm: A, f: F,       phantom: PhantomData<fn(I) -> M> }
+pub struct Filter<A,F,I>  { sm: A, f: F,       phantom: PhantomData<fn(I)> }
+pub struct Inspect<A,F,I> { sm: A, f: F,       phantom: PhantomData<fn(I)> }
+pub struct Tee<A,P,I,X>   { sm: A, side: P,    phantom: PhantomData<fn(I) -> X> }
+pub struct Stamp<A,I>     { sm: A, start: usize, t: usize, phantom: PhantomData<fn(I)> }
+
+impl<I,M,O,A,B> Push<I,O> for Then<A,B,I,M>
+    where A: Push<I,M>, B: Push<M,O>
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> {
+        match self.first.push(input) {
+            Some(m) => self.next.push(m),
+            None => None,
+        }
+    }
+    fn reset(&mut self) {
+        self.first.reset();
+        self.next.reset();
+    }
+    // Drain the first machine into the next before flushing it.
+    fn flush(&mut self) -> Option<O> {
+        while let Some(m) = self.first.flush() {
+            if let Some(o) = self.next.push(m) { return Some(o); }
+        }
+        self.next.flush()
+    }
+}
+impl<I,M,O,A,F> Push<I,O> for Map<A,F,I,M>
+    where A: Push<I,M>, F: FnMut(M) -> O
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> {
+        self.sm.push(input).map(&mut self.f)
+    }
+    fn reset(&mut self) { self.sm.reset() }
+    fn flush(&mut self) -> Option<O> {
+        self.sm.flush().map(&mut self.f)
+    }
+}
+impl<I,O,A,F> Push<I,O> for Filter<A,F,I>
+    where A: Push<I,O>, F: FnMut(&O) -> bool
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> {
+        self.sm.push(input).filter(&mut self.f)
+    }
+    fn reset(&mut self) { self.sm.reset() }
+    fn flush(&mut self) -> Option<O> {
+        while let Some(o) = self.sm.flush() {
+            if (self.f)(&o) { return Some(o); }
+        }
+        None
+    }
+}
+impl<I,O,A,F> Push<I,O> for Inspect<A,F,I>
+    where A: Push<I,O>, F: FnMut(&O)
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> {
+        let rv = self.sm.push(input);
+        if let Some(ref o) = rv { (self.f)(o); }
+        rv
+    }
+    fn reset(&mut self) { self.sm.reset() }
+    fn flush(&mut self) -> Option<O> {
+        let rv = self.sm.flush();
+        if let Some(ref o) = rv { (self.f)(o); }
+        rv
+    }
+}
+// Resync points are a property of the machine's input, so they are
+// not changed by operations on the output.
+impl<B,A,F,I,M> Resync<B> for Map<A,F,I,M> where A: Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        self.sm.resync(capture, from)
+    }
+}
+impl<B,A,F,I> Resync<B> for Filter<A,F,I> where A: Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        self.sm.resync(capture, from)
+    }
+}
+impl<B,A,F,I> Resync<B> for Inspect<A,F,I> where A: Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        self.sm.resync(capture, from)
+    }
+}
+
+impl<I,O,X,A,P> Push<I,O> for Tee<A,P,I,X>
+    where A: Push<I,O>, P: Push<O,X>, O: Clone
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<O> {
+        let rv = self.sm.push(input);
+        if let Some(ref o) = rv { self.side.push(o.clone()); }
+        rv
+    }
+    fn reset(&mut self) {
+        self.sm.reset();
+        self.side.reset();
+    }
+    fn flush(&mut self) -> Option<O> {
+        match self.sm.flush() {
+            Some(o) => {
+                self.side.push(o.clone());
+                Some(o)
+            },
+            None => {
+                while self.side.flush().is_some() {}
+                None
+            }
+        }
+    }
+}
+
+impl<A,I> Stamp<A,I> {
+    // Start counting at t, e.g. for a machine that is started in the
+    // middle of a capture.
+    pub fn starting_at(mut self, t: usize) -> Self {
+        self.start = t;
+        self.t = t;
+        self
+    }
+}
+impl<I,O,A> Push<I,(usize,O)> for Stamp<A,I>
+    where A: Push<I,O>
+{
+    #[inline(always)]
+    fn push(&mut self, input: I) -> Option<(usize,O)> {
+        let t = self.t;
+        self.t += 1;
+        self.sm.push(input).map(|o| (t, o))
+    }
+    fn reset(&mut self) {
+        self.sm.reset();
+        self.t = self.start;
+    }
+    fn flush(&mut self) -> Option<(usize,O)> {
+        let t = self.t;
+        self.sm.flush().map(|o| (t, o))
+    }
+}
+impl<B,A,I> Resync<B> for Stamp<A,I> where A: Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        self.sm.resync(capture, from)
+    }
+}
+// The state of the second machine is not known at resync points of
+// the first one.
+impl<B,A,N,I,M> Resync<B> for Then<A,N,I,M> {}
+impl<B,P> Resync<B> for Box<P> where P: ?Sized+Resync<B> {
+    fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+        (**self).resync(capture, from)
+    }
+}
+
+// Some state machines fall back into a known state after particular
+// input conditions, e.g. an idle line.  A freshly initialized machine
+// started at such a point produces the same output as one that has
+// been running since the start of the capture.  This allows a capture
+// to be split into chunks that are decoded independently.
+pub trait Resync<B> {
+    // Find the first resync point at or after index `from`.  The
+    // default is for machines without resync points.
+    fn resync(&self, _capture: &[B], _from: usize) -> Option<usize> { None }
+}
+
+// Protocol errors a machine has recovered from since the last reset,
+// as (name, count).  Errors are not part of the output, so a decoder
+// keeps its output type and errors are reported separately, e.g. at
+// the end of a capture.  Composites report the errors of all parts.
+pub trait Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)>;
+}
+// One line summary, e.g. "errors: framing 2, break 1".
+pub fn report(errors: &[(&'static str, usize)]) -> String {
+    let counts: Vec<String> = errors.iter()
+        .filter(|&&(_, n)| n > 0)
+        .map(|&(name, n)| format!("{} {}", name, n))
+        .collect();
+    if counts.is_empty() { "errors: none".to_string() }
+    else { format!("errors: {}", counts.join(", ")) }
+}
+impl<A,B,I,M> Errors for Then<A,B,I,M> where A: Errors, B: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> {
+        let mut rv = self.first.errors();
+        rv.extend(self.next.errors());
+        rv
+    }
+}
+impl<A,F,I,M> Errors for Map<A,F,I,M> where A: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { self.sm.errors() }
+}
+impl<A,F,I> Errors for Filter<A,F,I> where A: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { self.sm.errors() }
+}
+impl<A,F,I> Errors for Inspect<A,F,I> where A: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { self.sm.errors() }
+}
+impl<A,I> Errors for Stamp<A,I> where A: Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { self.sm.errors() }
+}
+impl<P> Errors for Box<P> where P: ?Sized+Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { (**self).errors() }
+}
+impl<P> Errors for &mut P where P: ?Sized+Errors {
+    fn errors(&self) -> Vec<(&'static str, usize)> { (**self).errors() }
 }
+
 // Many state machines operate on input busses.
 pub trait Bus {
-    fn channel(&self, usize) -> usize;
+    fn channel(&self, c: usize) -> usize;
     fn as_usize(&self) -> usize;
 }
 
@@ -46,7 +356,7 @@ impl_Bus!(u8);
 impl_Bus!(usize);
 impl_Bus!(i32);
 
-impl<'a,T> Bus for &'a T where T: Bus {
+impl<T> Bus for &T where T: Bus {
     #[inline(always)]
     fn channel(&self, c:usize) -> usize { (*self).channel(c) }
     fn as_usize(&self) -> usize { (*self).as_usize() }
@@ -56,9 +366,15 @@ impl<'a,T> Bus for &'a T where T: Bus {
 pub mod diff {
     use sm::Push;
     use sm::Bus;
+    use sm::Errors;
+    use sm::Resync;
     #[derive(Copy,Clone)]
     pub struct State { last: usize, }
     pub fn init() -> State {State{last: 0}}
+    impl State {
+        pub fn reset(&mut self) { *self = init(); }
+        pub fn flush(&mut self) -> Option<usize> { None }
+    }
 
     impl<B> Push<B,usize> for State where B: Bus {
         #[inline(always)]
@@ -68,13 +384,19 @@ pub mod diff {
             self.last  = input;
             if x == 0 { None } else { Some(input) }
         }
+        fn reset(&mut self) { State::reset(self) }
+        fn flush(&mut self) -> Option<usize> { State::flush(self) }
     }
+    impl Errors for State {
+        fn errors(&self) -> Vec<(&'static str, usize)> { vec![] }
+    }
+    impl<B> Resync<B> for State {}
 }
 
 pub mod uart {
 
     // Analyzer config and state data structures.
-    use sm::Push;
+    use sm::{Push,Resync,Errors,Bus};
     use self::Mode::*;
     
     #[derive(Copy,Clone)]
@@ -93,19 +415,23 @@ pub mod uart {
         skip: usize, // skip count to next sample point
         mode: Mode,
         clocks: usize,
+        framing: usize,  // stop bit low, word dropped
+        breaks: usize,   // stop bit low and all data bits 0
     }
     enum Mode {
         Idle, Shift, Break, FrameErr,
     }
     pub fn init(config: Config) -> Uart {
         Uart {
-            config: config,
+            config,
             state: State {
                 reg:  0,
                 bit:  0,
                 skip: 0,
                 mode: Idle,
                 clocks: 0,
+                framing: 0,
+                breaks: 0,
             },
         }
     }
@@ -120,7 +446,9 @@ pub mod uart {
     }
 
     // Process a single byte, output word when ready.
-    impl<B> Push<B,usize> for Uart where B: super::Bus {
+    impl<B> Push<B,usize> for Uart where B: Bus {
+        // Explicit returns, as in the C original.
+        #[allow(clippy::needless_return)]
         #[inline(always)]
         fn push(&mut self, input :B) -> Option<usize> {
             let s = &mut self.state;
@@ -160,10 +488,9 @@ pub mod uart {
                             return Some(s.reg);
                         }
                         else {
-                            eprintln!("FrameErr/Break 0x{:x}", s.reg);
                             s.mode = match s.reg {
-                                0 => Break,
-                                _ => FrameErr,
+                                0 => { s.breaks += 1; Break },
+                                _ => { s.framing += 1; FrameErr },
                             };
                             return None;
                         }
@@ -177,6 +504,53 @@ pub mod uart {
                 }
             }
         }
+        fn reset(&mut self) { Uart::reset(self) }
+        fn flush(&mut self) -> Option<usize> { Uart::flush(self) }
+    }
+
+    impl Uart {
+        pub fn reset(&mut self) {
+            *self = init(self.config);
+        }
+        // A word is reported if all data bits were shifted in and only
+        // the stop bit is missing.  Partial words are dropped.
+        pub fn flush(&mut self) -> Option<usize> {
+            let s = &mut self.state;
+            match s.mode {
+                Shift if s.bit == self.config.nb_bits => {
+                    s.mode = Idle;
+                    Some(s.reg)
+                },
+                _ => None
+            }
+        }
+    }
+
+    impl Errors for Uart {
+        fn errors(&self) -> Vec<(&'static str, usize)> {
+            vec![("framing", self.state.framing), ("break", self.state.breaks)]
+        }
+    }
+
+    // A line that has been idle for longer than a frame leaves the
+    // machine in Idle mode.  One extra bit period is added to cover
+    // the start delay.
+    impl<B> Resync<B> for Uart where B: Bus {
+        fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+            let c = &self.config;
+            let idle = (c.nb_bits + 3) * c.period;
+            let mut count = 0;
+            for (i, b) in capture.iter().enumerate().skip(from) {
+                if b.channel(c.channel) == 1 {
+                    count += 1;
+                    if count >= idle { return Some(i); }
+                }
+                else {
+                    count = 0;
+                }
+            }
+            None
+        }
     }
 }
 
@@ -192,7 +566,7 @@ pub mod syncser {
     //     full words, then allow endianness config in the output
     //     stream.
    
-    use sm::Push;
+    use sm::{Push,Resync,Errors,Bus};
 
     /* SPI clock configurations can be confusing as there are many
     ways to express the same information.  Thus uses the following
@@ -233,6 +607,7 @@ pub mod syncser {
         shift_count: usize,
         shift_reg: usize,
         frame_timeout_state: usize,
+        truncated: usize,  // partial words dropped at frame end or timeout
     }
     pub struct SyncSer {
         pub config: Config,
@@ -262,11 +637,14 @@ pub mod syncser {
                 frame_timeout_state: 0,
                 shift_count: 0,
                 shift_reg: 0,
+                truncated: 0,
             }
         }
     }
 
-    impl<B> Push<B,usize> for SyncSer where B: super::Bus {
+    impl<B> Push<B,usize> for SyncSer where B: Bus {
+        // One condition per nesting level, as in the C original.
+        #[allow(clippy::collapsible_if, clippy::needless_return)]
         #[inline(always)]
         fn push(&mut self, input :B) -> Option<usize> {   
 
@@ -281,7 +659,8 @@ pub mod syncser {
 
             // Frame edge
             // FIXME: this should wait to do anything if it starts in the
-            // middle of a frame.
+            // middle of a frame.  A capture that does is likely to end
+            // the first frame with a partial word, which is counted.
             if c.frame_enable {
                 if frame_bit != s.frame_state { // transition
                     if frame_bit == c.frame_active {
@@ -289,6 +668,11 @@ pub mod syncser {
                         s.shift_reg = 0;
                         s.shift_count = 0;
                     }
+                    else if s.shift_count != 0 {
+                        s.truncated += 1;
+                        s.shift_reg = 0;
+                        s.shift_count = 0;
+                    }
                 }
             }
             
@@ -297,6 +681,7 @@ pub mod syncser {
                 if c.frame_timeout > 0 {
                     if s.frame_timeout_state == 0 {
                         // reset
+                        if s.shift_count != 0 { s.truncated += 1; }
                         s.shift_reg = 0;
                         s.shift_count = 0;
                         s.frame_timeout_state = c.frame_timeout;
@@ -332,11 +717,53 @@ pub mod syncser {
 
             return rv;
         }
+        fn reset(&mut self) { SyncSer::reset(self) }
+        fn flush(&mut self) -> Option<usize> { SyncSer::flush(self) }
+    }
+
+    impl SyncSer {
+        pub fn reset(&mut self) {
+            *self = init(self.config);
+        }
+        // Report a partial word, unless the frame it belongs to has
+        // already ended.
+        pub fn flush(&mut self) -> Option<usize> {
+            let s = &mut self.state;
+            let c = &self.config;
+            if s.shift_count == 0 { return None; }
+            if c.frame_enable && s.frame_state != c.frame_active { return None; }
+            let rv = s.shift_reg;
+            s.shift_reg = 0;
+            s.shift_count = 0;
+            Some(rv)
+        }
+    }
+
+    impl Errors for SyncSer {
+        fn errors(&self) -> Vec<(&'static str, usize)> {
+            vec![("truncated", self.state.truncated)]
+        }
+    }
+
+    // An inactive frame line resets the word boundary, so any point
+    // where it is inactive can serve as a resync point.  Without frame
+    // signal there is no way to recover word alignment.  The frame
+    // timeout counter is not part of the resync condition, so that
+    // mode is not supported.
+    impl<B> Resync<B> for SyncSer where B: Bus {
+        fn resync(&self, capture: &[B], from: usize) -> Option<usize> {
+            let c = &self.config;
+            if !c.frame_enable || c.timeout_enable { return None; }
+            capture.iter().skip(from)
+                .position(|b| b.channel(c.frame_channel) != c.frame_active)
+                .map(|i| from + i)
+        }
     }
 }
 pub mod slip {
     use sm::Push;
     use sm::Bus;
+    use sm::Errors;
     use std::mem;
     
     #[derive(Copy,Clone)]
@@ -349,6 +776,7 @@ pub mod slip {
     pub struct State {
         buf: Vec<u8>,
         esc: bool,
+        escapes: usize,  // escape followed by an unknown character
     }
     pub struct Slip {
         config: Config,
@@ -360,6 +788,7 @@ pub mod slip {
             state: State {
                 buf: Vec::new(),
                 esc: false,
+                escapes: 0,
             }
         }
     }
@@ -371,26 +800,49 @@ pub mod slip {
             let i = input_bus.as_usize() as u8;
             if s.esc {
                 s.esc = false;
-                if      c.esc_end == i { s.buf.push(c.end); }
-                else if c.esc_esc == i { s.buf.push(c.esc); }
-                return None;
+                if      c.esc_end == i { s.buf.push(c.end); return None; }
+                else if c.esc_esc == i { s.buf.push(c.esc); return None; }
+                // Bad escape.  The character is dropped, except for an
+                // end character, which still ends the packet.
+                s.escapes += 1;
+                if c.end != i { return None; }
             }
             if c.esc == i {
                 s.esc = true;
                 return None;
             }
             if c.end == i {
-                let packet = mem::replace(&mut s.buf, Vec::new());
+                let packet = mem::take(&mut s.buf);
                 return Some(packet);
             }
             s.buf.push(i);
-            return None;
+            None
+        }
+        fn reset(&mut self) { Slip::reset(self) }
+        fn flush(&mut self) -> Option<Vec<u8>> { Slip::flush(self) }
+    }
+
+    impl Slip {
+        pub fn reset(&mut self) {
+            *self = init(self.config);
+        }
+        // Report a packet that was not terminated.
+        pub fn flush(&mut self) -> Option<Vec<u8>> {
+            let s = &mut self.state;
+            s.esc = false;
+            if s.buf.is_empty() { return None; }
+            Some(mem::take(&mut s.buf))
+        }
+    }
+    impl Errors for Slip {
+        fn errors(&self) -> Vec<(&'static str, usize)> {
+            vec![("escape", self.state.escapes)]
         }
     }
     pub fn print(v: Vec<u8>) {
         print!("({}) -", v.len());
         for e in v { print!(" {:01$x}", e, 2); }
-        println!("");
+        println!();
     }
 }
 
@@ -398,3 +850,405 @@ pub mod slip {
 
 
 
+
+pub mod deglitch {
+    // Suppress pulses that are shorter than a minimum width, configured
+    // per channel.  Deciding whether an edge is a glitch needs
+    // lookahead, so the output is delayed by the largest width.  All
+    // channels are delayed by the same amount, so relative timing is
+    // preserved.  The output is a cleaned bus sample that can be fed
+    // into any of the other machines.
+
+    use sm::{Push,Bus};
+    use std::collections::VecDeque;
+
+    #[derive(Clone)]
+    pub struct Config {
+        // Minimum pulse width in samples, indexed by channel, at most
+        // one per bus bit.  Widths 0 and 1 disable filtering for that
+        // channel.
+        pub widths: Vec<usize>,
+    }
+    pub struct Deglitch {
+        pub config: Config,
+        mask: usize,          // filtered channels
+        delay: usize,
+        buf: VecDeque<usize>, // current sample + lookahead
+        out: usize,           // output level of filtered channels
+        prev: usize,          // previous input sample
+        started: bool,
+        glitches: Vec<usize>, // suppressed pulses per channel
+    }
+    pub fn init(config: Config) -> Deglitch {
+        let mut mask = 0;
+        for (c, &w) in config.widths.iter().enumerate() {
+            if w > 1 { mask |= 1 << c; }
+        }
+        let delay = config.widths.iter().cloned().max().unwrap_or(1).max(1) - 1;
+        let nb_channels = config.widths.len();
+        Deglitch {
+            config,
+            mask,
+            delay,
+            buf: VecDeque::with_capacity(delay + 1),
+            out: 0,
+            prev: 0,
+            started: false,
+            glitches: vec![0; nb_channels],
+        }
+    }
+
+    impl Deglitch {
+        // Number of suppressed pulses, indexed by channel.
+        pub fn glitches(&self) -> &[usize] {
+            &self.glitches
+        }
+        pub fn report(&self) -> String {
+            let counts: Vec<String> = self.glitches.iter().enumerate()
+                .filter(|&(_, &n)| n > 0)
+                .map(|(c, n)| format!("ch{}: {}", c, n))
+                .collect();
+            if counts.is_empty() { "glitches: none".to_string() }
+            else { format!("glitches: {}", counts.join(", ")) }
+        }
+        pub fn reset(&mut self) {
+            *self = init(self.config.clone());
+        }
+        // Drains the delay line.
+        pub fn flush(&mut self) -> Option<usize> {
+            self.output()
+        }
+
+        // Produce the output for the oldest sample in the buffer.  An
+        // edge is accepted if the new level persists for the minimum
+        // width.  At end of input that is relaxed to the remaining
+        // samples.
+        #[inline(always)]
+        fn output(&mut self) -> Option<usize> {
+            let x = self.buf.pop_front()?;
+            if !self.started {
+                self.started = true;
+                self.out = x;
+                self.prev = x;
+            }
+            let edges = (x ^ self.out) & self.mask;
+            if edges != 0 {
+                for (c, &w) in self.config.widths.iter().enumerate() {
+                    if (edges >> c) & 1 == 0 { continue; }
+                    let bit = x.channel(c);
+                    let n = (w - 1).min(self.buf.len());
+                    if self.buf.iter().take(n).all(|b| b.channel(c) == bit) {
+                        self.out ^= 1 << c;
+                    }
+                    else if self.prev.channel(c) != bit {
+                        self.glitches[c] += 1;
+                    }
+                }
+            }
+            self.prev = x;
+            Some((x & !self.mask) | (self.out & self.mask))
+        }
+    }
+
+    impl<B> Push<B,usize> for Deglitch where B: Bus {
+        #[inline(always)]
+        fn push(&mut self, input: B) -> Option<usize> {
+            if self.mask == 0 { return Some(input.as_usize()); }
+            self.buf.push_back(input.as_usize());
+            if self.buf.len() <= self.delay { return None; }
+            self.output()
+        }
+        fn reset(&mut self) { Deglitch::reset(self) }
+        fn flush(&mut self) -> Option<usize> { Deglitch::flush(self) }
+    }
+}
+
+pub mod remap {
+    // Bus transformation: permute, invert and mask channels, so probes
+    // can be wired arbitrarily while decoder configurations refer to
+    // logical channels.  Output channel i is taken from input channel
+    // map[i].  Without map, channels are passed through in place.
+    // Inversion and mask apply to output channels.
+
+    use sm::{Push,Bus};
+
+    #[derive(Clone)]
+    pub struct Config {
+        pub map:    Option<Vec<usize>>,
+        pub invert: usize,
+        pub mask:   usize,
+    }
+    pub struct Remap {
+        pub config: Config,
+    }
+    pub fn config() -> Config {
+        Config { map: None, invert: 0, mask: !0 }
+    }
+    pub fn init(config: Config) -> Remap {
+        Remap { config }
+    }
+    impl Remap {
+        pub fn is_identity(&self) -> bool {
+            let c = &self.config;
+            c.map.is_none() && c.invert == 0 && c.mask == !0
+        }
+        #[inline(always)]
+        pub fn apply(&self, input: usize) -> usize {
+            let c = &self.config;
+            let permuted = match c.map {
+                None => input,
+                Some(ref map) => {
+                    let mut out = 0;
+                    for (i, &from) in map.iter().enumerate() {
+                        out |= input.channel(from) << i;
+                    }
+                    out
+                }
+            };
+            (permuted ^ c.invert) & c.mask
+        }
+    }
+    impl<B> Push<B,usize> for Remap where B: Bus {
+        #[inline(always)]
+        fn push(&mut self, input: B) -> Option<usize> {
+            Some(self.apply(input.as_usize()))
+        }
+        fn reset(&mut self) { }
+        fn flush(&mut self) -> Option<usize> { None }
+    }
+}
+
+pub mod measure {
+    // Timing measurements per channel, in samples: edge counts, high
+    // and low pulse widths and the period between rising edges.  A
+    // pulse is counted when it ends, so the partial pulses at the
+    // start and end of the capture are not included.
+    //
+    // Statistics cover the whole capture and are produced on flush,
+    // or a sliding window of the last `window` samples, reported every
+    // `step` samples.  The window keeps the events it covers, the
+    // whole capture only keeps the totals.
+
+    use sm::{Push,Bus};
+    use std::collections::VecDeque;
+
+    #[derive(Clone)]
+    pub struct Config {
+        pub channels: usize,  // channels 0 up to this
+        pub window: usize,    // 0 for the whole capture
+        pub step: usize,      // report interval, 0 means window
+    }
+
+    // Histogram bin k counts values from 2^k up to 2^(k+1).
+    #[derive(Clone,Default,PartialEq,Debug)]
+    pub struct Stats {
+        pub count: usize,
+        pub min: usize,
+        pub max: usize,
+        pub sum: usize,
+        pub hist: Vec<usize>,
+    }
+    impl Stats {
+        pub fn add(&mut self, x: usize) {
+            if self.count == 0 || x < self.min { self.min = x; }
+            if x > self.max { self.max = x; }
+            self.count += 1;
+            self.sum += x;
+            let bin = (usize::BITS - x.leading_zeros()).saturating_sub(1) as usize;
+            if self.hist.len() <= bin { self.hist.resize(bin + 1, 0); }
+            self.hist[bin] += 1;
+        }
+        pub fn mean(&self) -> Option<f64> {
+            if self.count == 0 { None } else { Some(self.sum as f64 / self.count as f64) }
+        }
+    }
+
+    #[derive(Clone,Default,PartialEq,Debug)]
+    pub struct Summary {
+        pub rising: usize,
+        pub falling: usize,
+        pub high: Stats,
+        pub low: Stats,
+        pub period: Stats,
+    }
+    impl Summary {
+        // From the mean period, in Hz.
+        pub fn frequency(&self, samplerate: usize) -> Option<f64> {
+            self.period.mean().map(|p| samplerate as f64 / p)
+        }
+        // Fraction of time high, over the complete pulses.
+        pub fn duty(&self) -> Option<f64> {
+            let total = self.high.sum + self.low.sum;
+            if total == 0 { None } else { Some(self.high.sum as f64 / total as f64) }
+        }
+        fn add(&mut self, event: Event) {
+            match event {
+                Event::Rising    => self.rising += 1,
+                Event::Falling   => self.falling += 1,
+                Event::High(w)   => self.high.add(w),
+                Event::Low(w)    => self.low.add(w),
+                Event::Period(w) => self.period.add(w),
+            }
+        }
+    }
+
+    // Measurements over samples start..end, indexed by channel.
+    #[derive(Clone,PartialEq,Debug)]
+    pub struct Report {
+        pub start: usize,
+        pub end: usize,
+        pub channels: Vec<Summary>,
+    }
+
+    #[derive(Copy,Clone)]
+    enum Event {
+        Rising, Falling, High(usize), Low(usize), Period(usize),
+    }
+
+    #[derive(Clone,Default)]
+    struct Channel {
+        last_edge: Option<usize>,
+        last_rise: Option<usize>,
+        events: VecDeque<(usize, Event)>,  // window mode
+        total: Summary,                    // whole capture
+    }
+
+    pub struct Measure {
+        pub config: Config,
+        t: usize,
+        last: usize,
+        reported: usize,
+        channels: Vec<Channel>,
+    }
+    pub fn init(config: Config) -> Measure {
+        let channels = vec![Channel::default(); config.channels];
+        Measure { config, t: 0, last: 0, reported: 0, channels }
+    }
+
+    impl Measure {
+        pub fn reset(&mut self) {
+            *self = init(self.config.clone());
+        }
+        // Report the samples not yet covered by a report.
+        pub fn flush(&mut self) -> Option<Report> {
+            if self.t > self.reported { Some(self.report()) } else { None }
+        }
+        fn record(&mut self, c: usize, t: usize, event: Event) {
+            let ch = &mut self.channels[c];
+            if self.config.window == 0 { ch.total.add(event); }
+            else { ch.events.push_back((t, event)); }
+        }
+        fn edge(&mut self, c: usize, t: usize, level: usize) {
+            let (last_edge, last_rise) = (self.channels[c].last_edge, self.channels[c].last_rise);
+            if let Some(e) = last_edge {
+                self.record(c, t, if level == 1 { Event::Low(t - e) } else { Event::High(t - e) });
+            }
+            if level == 1 {
+                self.record(c, t, Event::Rising);
+                if let Some(r) = last_rise { self.record(c, t, Event::Period(t - r)); }
+                self.channels[c].last_rise = Some(t);
+            }
+            else {
+                self.record(c, t, Event::Falling);
+            }
+            self.channels[c].last_edge = Some(t);
+        }
+        fn report(&mut self) -> Report {
+            let end = self.t;
+            let start = if self.config.window == 0 { 0 } else { end.saturating_sub(self.config.window) };
+            self.reported = end;
+            let window = self.config.window;
+            let channels = self.channels.iter_mut().map(|ch| {
+                if window == 0 { return ch.total.clone(); }
+                while let Some(&(t, _)) = ch.events.front() {
+                    if t >= start { break; }
+                    ch.events.pop_front();
+                }
+                let mut summary = Summary::default();
+                for &(_, event) in ch.events.iter() { summary.add(event); }
+                summary
+            }).collect();
+            Report { start, end, channels }
+        }
+    }
+
+    impl<B> Push<B,Report> for Measure where B: Bus {
+        fn push(&mut self, input: B) -> Option<Report> {
+            let x = input.as_usize();
+            let t = self.t;
+            self.t += 1;
+            let edges = if t == 0 { 0 } else { x ^ self.last };
+            self.last = x;
+            if edges != 0 {
+                for c in 0..self.config.channels {
+                    if edges.channel(c) == 1 { self.edge(c, t, x.channel(c)); }
+                }
+            }
+            let step = if self.config.step == 0 { self.config.window } else { self.config.step };
+            if step > 0 && self.t.is_multiple_of(step) { Some(self.report()) } else { None }
+        }
+        fn reset(&mut self) { Measure::reset(self) }
+        fn flush(&mut self) -> Option<Report> { Measure::flush(self) }
+    }
+
+    // Time in seconds with a unit, or in samples if the samplerate is
+    // not known.
+    pub fn time(samples: f64, samplerate: usize) -> String {
+        if samplerate == 0 { return format!("{:.1}", samples); }
+        let s = samples / samplerate as f64;
+        let (unit, name) = [(1.0, "s"), (1e-3, "ms"), (1e-6, "us")].iter().cloned()
+            .find(|&(u, _)| s >= u).unwrap_or((1e-9, "ns"));
+        format!("{:.3}{}", s / unit, name)
+    }
+    pub fn frequency(hz: f64) -> String {
+        let (unit, name) = [(1e6, "MHz"), (1e3, "kHz")].iter().cloned()
+            .find(|&(u, _)| hz >= u).unwrap_or((1.0, "Hz"));
+        format!("{:.3}{}", hz / unit, name)
+    }
+
+    impl Report {
+        // One line per channel.  Pulse widths and period are given as
+        // min/mean/max.
+        pub fn table(&self, samplerate: usize) -> String {
+            let stats = |s: &Stats| match s.mean() {
+                None => "-".to_string(),
+                Some(mean) => format!("{}/{}/{}", time(s.min as f64, samplerate),
+                                      time(mean, samplerate), time(s.max as f64, samplerate)),
+            };
+            let mut rv = format!("samples {}..{}\n", self.start, self.end);
+            rv.push_str(&format!("{:>3} {:>8} {:>8} {:>12} {:>6}  {:32} {:32} {}\n",
+                                 "ch", "rising", "falling", "frequency", "duty",
+                                 "period", "high", "low"));
+            for (c, s) in self.channels.iter().enumerate() {
+                let freq = match s.frequency(samplerate) {
+                    Some(f) if samplerate > 0 => frequency(f),
+                    _ => "-".to_string(),
+                };
+                let duty = match s.duty() {
+                    Some(d) => format!("{:.1}%", 100.0 * d),
+                    None => "-".to_string(),
+                };
+                rv.push_str(&format!("{:>3} {:>8} {:>8} {:>12} {:>6}  {:32} {:32} {}\n",
+                                     c, s.rising, s.falling, freq, duty,
+                                     stats(&s.period), stats(&s.high), stats(&s.low)));
+            }
+            rv
+        }
+        // Histogram bins per channel and measurement, e.g. "2^3:5" for
+        // 5 values from 8 up to 16 samples.
+        pub fn histogram(&self) -> String {
+            let mut rv = String::new();
+            for (c, s) in self.channels.iter().enumerate() {
+                for (name, stats) in [("period", &s.period), ("high", &s.high), ("low", &s.low)] {
+                    if stats.count == 0 { continue; }
+                    let bins: Vec<String> = stats.hist.iter().enumerate()
+                        .filter(|&(_, &n)| n > 0)
+                        .map(|(k, n)| format!("2^{}:{}", k, n))
+                        .collect();
+                    rv.push_str(&format!("{:>3} {:6} {}\n", c, name, bins.join(" ")));
+                }
+            }
+            rv
+        }
+    }
+}
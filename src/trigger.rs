@@ -0,0 +1,306 @@
+/* trigger: Select parts of a stream around trigger events.
+
+A trigger is a sequence of stages.  Each stage is a condition that is
+checked from the sample after the previous stage matched, and the
+trigger fires on the sample where the last stage matches.  The
+sequence then starts over.  Stages are separated by '/', e.g.
+
+  trigger=1XR/uart:0x7e
+
+Conditions:
+
+  01XRF..              pattern, one character per channel starting at
+                       channel 0: 0 low, 1 high, X any, R rising, F
+                       falling edge.  Lower case is accepted
+  w:<ch>:<h|l>:<min>[:<max>]
+                       a high or low pulse with a width in samples from
+                       min up to and including max, matched at the edge
+                       that ends the pulse
+  <decoder>[:<value>]  the decoder produces an item, or an item equal
+                       to value: a byte or word, or the first byte of
+                       a packet
+
+Decoders in conditions see all samples, not only those after the
+previous stage, so they stay in sync with the stream.
+
+The gate passes samples around trigger events: pre samples before and
+post samples after.  The samples before the trigger are kept in a
+ring buffer, so the output is delayed by pre samples, as in
+sm::deglitch.  Output samples carry their index in the input stream,
+so a gap in the indices marks the start of a new segment.  The
+trigger is re-armed once the post-trigger samples have passed.  With
+a trigger count, apply() stops reading input once the last window has
+passed, so a live capture ends.
+
+*/
+
+use decoder::{Registry,Options,Item,Decoder,Error};
+use sm::{Push,Bus};
+use std::collections::VecDeque;
+use std::iter;
+
+pub enum Cond {
+    Pattern { mask: usize, value: usize, rising: usize, falling: usize },
+    Pulse { channel: usize, level: usize, min: usize, max: usize },
+    Decoder { decoder: Box<dyn Decoder>, value: Option<usize> },
+}
+
+fn bad(spec: &str, reason: &str) -> Error {
+    Error::BadOption("trigger".to_string(), format!("{}: {}", spec, reason))
+}
+
+fn number(spec: &str, field: &str) -> Result<usize, Error> {
+    let rv = match field.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => field.parse::<usize>(),
+    };
+    rv.map_err(|e| bad(spec, &e.to_string()))
+}
+
+fn is_pattern(spec: &str) -> bool {
+    !spec.is_empty() && spec.chars().all(|c| "01xXrRfF".contains(c))
+}
+
+// A single stage.  Decoders are created from the registry with the
+// given options.
+pub fn cond(spec: &str, registry: &Registry, options: &Options) -> Result<Cond, Error> {
+    if is_pattern(spec) {
+        if spec.len() > usize::BITS as usize {
+            return Err(bad(spec, "too many channels"));
+        }
+        let (mut mask, mut value, mut rising, mut falling) = (0, 0, 0, 0);
+        for (c, ch) in spec.chars().enumerate() {
+            match ch {
+                '0' => mask |= 1 << c,
+                '1' => { mask |= 1 << c; value |= 1 << c; },
+                'r' | 'R' => rising |= 1 << c,
+                'f' | 'F' => falling |= 1 << c,
+                _ => (),
+            }
+        }
+        return Ok(Cond::Pattern { mask, value, rising, falling });
+    }
+    let fields: Vec<&str> = spec.split(':').collect();
+    if fields[0] == "w" {
+        if fields.len() < 4 || fields.len() > 5 {
+            return Err(bad(spec, "expected w:<ch>:<h|l>:<min>[:<max>]"));
+        }
+        let level = match fields[2] {
+            "h" => 1,
+            "l" => 0,
+            _ => return Err(bad(spec, "level is h or l")),
+        };
+        let max = match fields.get(4) {
+            Some(max) if !max.is_empty() => number(spec, max)?,
+            _ => usize::MAX,
+        };
+        let channel = number(spec, fields[1])?;
+        if channel >= usize::BITS as usize {
+            return Err(bad(spec, "channel out of range"));
+        }
+        return Ok(Cond::Pulse {
+            channel,
+            level,
+            min: number(spec, fields[3])?,
+            max,
+        });
+    }
+    if fields.len() > 2 {
+        return Err(bad(spec, "expected <decoder>[:<value>]"));
+    }
+    let value = match fields.get(1) {
+        Some(v) => Some(number(spec, v)?),
+        None => None,
+    };
+    Ok(Cond::Decoder { decoder: registry.create(fields[0], options)?, value })
+}
+
+pub struct Trigger {
+    stages: Vec<Cond>,
+    stage: usize,
+    t: usize,
+    last: usize,
+    edges: Vec<usize>,  // time of the last edge per channel, for pulses
+}
+
+pub fn init(stages: Vec<Cond>) -> Trigger {
+    Trigger { stages, stage: 0, t: 0, last: 0, edges: vec![] }
+}
+
+pub fn parse(spec: &str, registry: &Registry, options: &Options) -> Result<Trigger, Error> {
+    let mut stages = vec![];
+    for stage in spec.split('/') {
+        stages.push(cond(stage, registry, options)?);
+    }
+    Ok(init(stages))
+}
+
+impl Trigger {
+    pub fn reset(&mut self) {
+        for stage in self.stages.iter_mut() {
+            if let Cond::Decoder { ref mut decoder, .. } = *stage { decoder.reset(); }
+        }
+        self.stage = 0;
+        self.t = 0;
+        self.last = 0;
+        self.edges.clear();
+    }
+    pub fn flush(&mut self) -> Option<usize> {
+        None
+    }
+    // Index of the current stage.
+    pub fn stage(&self) -> usize {
+        self.stage
+    }
+}
+
+// Output is the index of the sample where the trigger fired.
+impl<B> Push<B,usize> for Trigger where B: Bus {
+    fn push(&mut self, input: B) -> Option<usize> {
+        let x = input.as_usize();
+        let t = self.t;
+        self.t += 1;
+        let changed = if t == 0 { 0 } else { x ^ self.last };
+        let (last, stage) = (self.last, self.stage);
+        self.last = x;
+        let mut hit = false;
+        for (i, cond) in self.stages.iter_mut().enumerate() {
+            let matched = match *cond {
+                Cond::Pattern { mask, value, rising, falling } =>
+                    x & mask == value
+                    && (changed & x) & rising == rising
+                    && (changed & !x) & falling == falling,
+                Cond::Pulse { channel, level, min, max } => {
+                    if self.edges.len() <= channel { self.edges.resize(channel + 1, usize::MAX); }
+                    let edge = self.edges[channel];
+                    if changed.channel(channel) == 0 { false }
+                    else {
+                        self.edges[channel] = t;
+                        edge != usize::MAX && last.channel(channel) == level
+                            && t - edge >= min && t - edge <= max
+                    }
+                },
+                Cond::Decoder { ref mut decoder, value } => match (decoder.push(x), value) {
+                    (None, _) => false,
+                    (Some(_), None) => true,
+                    (Some(Item::Byte(b)), Some(v)) => b as usize == v,
+                    (Some(Item::Word(w)), Some(v)) => w == v,
+                    (Some(Item::Packet(p)), Some(v)) => p.first().map(|&b| b as usize) == Some(v),
+                },
+            };
+            if i == stage { hit = matched; }
+        }
+        if !hit { return None; }
+        self.stage += 1;
+        if self.stage < self.stages.len() { return None; }
+        self.stage = 0;
+        Some(t)
+    }
+    fn reset(&mut self) { Trigger::reset(self) }
+    fn flush(&mut self) -> Option<usize> { Trigger::flush(self) }
+}
+
+#[derive(Copy,Clone)]
+pub struct Config {
+    pub pre: usize,
+    pub post: usize,
+    pub count: usize,  // number of triggers, 0 for no limit
+}
+
+pub struct Gate {
+    pub config: Config,
+    trigger: Trigger,
+    buf: VecDeque<usize>,   // the last pre samples and the current one
+    t: usize,               // index of the next input sample
+    window: Option<(usize, usize)>,  // samples to pass, inclusive
+    fired: usize,
+}
+
+pub fn gate(trigger: Trigger, config: Config) -> Gate {
+    Gate {
+        config,
+        trigger,
+        buf: VecDeque::with_capacity(config.pre + 1),
+        t: 0,
+        window: None,
+        fired: 0,
+    }
+}
+
+impl Gate {
+    pub fn reset(&mut self) {
+        self.trigger.reset();
+        self.buf.clear();
+        self.t = 0;
+        self.window = None;
+        self.fired = 0;
+    }
+    // Drains the ring buffer.
+    pub fn flush(&mut self) -> Option<(usize, usize)> {
+        while !self.buf.is_empty() {
+            if let Some(out) = self.output() { return Some(out); }
+        }
+        None
+    }
+    // Number of times the trigger fired.
+    pub fn fired(&self) -> usize {
+        self.fired
+    }
+    // All count triggers fired and the last window has been output.
+    pub fn done(&self) -> bool {
+        let c = &self.config;
+        match self.window {
+            Some((_, to)) => c.count > 0 && self.fired >= c.count && self.t - self.buf.len() > to,
+            None => false,
+        }
+    }
+    fn output(&mut self) -> Option<(usize, usize)> {
+        let x = self.buf.pop_front()?;
+        let t = self.t - self.buf.len() - 1;
+        match self.window {
+            Some((from, to)) if t >= from && t <= to => Some((t, x)),
+            _ => None,
+        }
+    }
+}
+
+// Output is the input sample index and the sample.
+impl<B> Push<B,(usize,usize)> for Gate where B: Bus {
+    fn push(&mut self, input: B) -> Option<(usize, usize)> {
+        let x = input.as_usize();
+        let t = self.t;
+        self.t += 1;
+        self.buf.push_back(x);
+        let fired = self.trigger.push(x).is_some();
+        let armed = match self.window {
+            Some((_, to)) => t > to,
+            None => true,
+        };
+        let c = &self.config;
+        if fired && armed && (c.count == 0 || self.fired < c.count) {
+            self.fired += 1;
+            self.window = Some((t.saturating_sub(c.pre), t + c.post));
+        }
+        if self.buf.len() <= c.pre { return None; }
+        self.output()
+    }
+    fn reset(&mut self) { Gate::reset(self) }
+    fn flush(&mut self) -> Option<(usize, usize)> { Gate::flush(self) }
+}
+
+// Like sm::apply, but stops reading input once the gate is done.
+pub fn apply<'a,B,Ins>(gate: &'a mut Gate, mut ins: Ins) -> impl 'a+Iterator<Item=(usize,usize)>
+    where B: 'a+Bus,
+          Ins: 'a+Iterator<Item=B>
+{
+    let mut eof = false;
+    iter::from_fn(move || {
+        while !eof && !gate.done() {
+            match ins.next() {
+                Some(x) => if let Some(o) = gate.push(x) { return Some(o); },
+                None => eof = true,
+            }
+        }
+        gate.flush()
+    })
+}
@@ -0,0 +1,424 @@
+/* vcd: Value Change Dump files.
+
+Conversion of captures to VCD, for viewing in e.g. GTKWave.  Each bus
+channel becomes a 1-bit wire.  Decoder output can be added as extra
+vector or string signals.  String signals are a GTKWave extension.
+
+Time is expressed in samples.  The timescale is picked such that a
+sample period is an integer number of time units.
+
+The Reader goes the other way: selected wires of a VCD file, e.g. from
+a simulation, are resampled at a fixed sample rate into a bus sample
+iterator, so all decoders can run on simulated waveforms.
+
+*/
+
+use sm::{Push,Bus,diff};
+use std::io::{self,Write,BufRead};
+use std::collections::{HashMap,VecDeque};
+
+// Largest unit for which the sample period is an integer.  Returns
+// the number of units per sample and the unit name.
+pub fn timescale(samplerate: usize) -> (u64, &'static str) {
+    let units: [(u64, &'static str); 6] = [
+        (1, "s"), (1_000, "ms"), (1_000_000, "us"),
+        (1_000_000_000, "ns"), (1_000_000_000_000, "ps"),
+        (1_000_000_000_000_000, "fs")];
+    let sr = samplerate.max(1) as u64;
+    for &(per_second, unit) in units.iter() {
+        if per_second % sr == 0 { return (per_second / sr, unit); }
+    }
+    // Rounded.  Only for sample rates that do not divide 1e15.
+    (1_000_000_000_000_000 / sr, "fs")
+}
+
+// Short identifier codes, using printable characters 33-126.
+fn id(mut n: usize) -> String {
+    let mut rv = String::new();
+    loop {
+        rv.push((33 + (n % 94)) as u8 as char);
+        n /= 94;
+        if n == 0 { return rv; }
+        n -= 1;
+    }
+}
+
+#[derive(Copy,Clone,PartialEq,Debug)]
+pub enum Kind {
+    Wire,
+    Vector(usize),  // number of bits
+    String,
+}
+
+struct Signal {
+    name: String,
+    kind: Kind,
+    id:   String,
+}
+
+pub struct Writer<W: Write> {
+    out: W,
+    step: u64,
+    unit: &'static str,
+    nb_channels: usize,
+    signals: Vec<Signal>,
+    diff: diff::State,
+    last: usize,   // last written bus value
+    dumped: bool,  // all channels written once
+    time: Option<u64>,
+    header: bool,
+}
+
+impl<W: Write> Writer<W> {
+    // Channel names are in channel order, starting at 0.
+    pub fn new(out: W, samplerate: usize, channels: &[String]) -> Writer<W> {
+        let (step, unit) = timescale(samplerate);
+        let mut w = Writer {
+            out, step, unit,
+            nb_channels: channels.len(),
+            signals: vec![],
+            diff: diff::init(),
+            last: 0,
+            dumped: false,
+            time: None,
+            header: false,
+        };
+        for name in channels {
+            w.add(name, Kind::Wire);
+        }
+        w
+    }
+    // Add a signal for decoder output.  Needs to be done before the
+    // first sample is written.  Returns the signal index.
+    pub fn add(&mut self, name: &str, kind: Kind) -> usize {
+        assert!(!self.header);
+        let n = self.signals.len();
+        self.signals.push(Signal {
+            name: name.replace(' ', "_"),
+            kind,
+            id: id(n),
+        });
+        n
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.header = true;
+        writeln!(self.out, "$version logan $end")?;
+        writeln!(self.out, "$timescale 1 {} $end", self.unit)?;
+        writeln!(self.out, "$scope module logan $end")?;
+        for s in self.signals.iter() {
+            let (typ, width) = match s.kind {
+                Kind::Wire      => ("wire", 1),
+                Kind::Vector(n) => ("wire", n),
+                Kind::String    => ("string", 1),
+            };
+            writeln!(self.out, "$var {} {} {} {} $end", typ, width, s.id, s.name)?;
+        }
+        writeln!(self.out, "$upscope $end")?;
+        writeln!(self.out, "$enddefinitions $end")
+    }
+
+    // Write a timestamp if time has advanced.  t is in samples.
+    fn at(&mut self, t: u64) -> io::Result<()> {
+        if !self.header { self.write_header()?; }
+        match self.time {
+            Some(last) if t <= last => (),
+            _ => {
+                writeln!(self.out, "#{}", t * self.step)?;
+                self.time = Some(t);
+            }
+        }
+        Ok(())
+    }
+
+    // Bus sample t.  Only changed channels are written, except for the
+    // first sample, which dumps all channels.
+    pub fn sample<B: Bus>(&mut self, t: u64, bus: B) -> io::Result<()> {
+        let bus = bus.as_usize();
+        let changed = match (self.dumped, self.diff.push(bus)) {
+            (false, _)      => !0,
+            (true, Some(b)) => b ^ self.last,
+            (true, None)    => return Ok(()),
+        };
+        self.dumped = true;
+        self.last = bus;
+        self.at(t)?;
+        for c in 0..self.nb_channels {
+            if (changed >> c) & 1 == 1 {
+                writeln!(self.out, "{}{}", bus.channel(c), self.signals[c].id)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Decoder output at sample t.
+    pub fn word(&mut self, t: u64, signal: usize, value: usize) -> io::Result<()> {
+        self.at(t)?;
+        let s = &self.signals[signal];
+        match s.kind {
+            Kind::Wire => writeln!(self.out, "{}{}", value & 1, s.id),
+            Kind::Vector(_) => writeln!(self.out, "b{:b} {}", value, s.id),
+            Kind::String => writeln!(self.out, "s{:x} {}", value, s.id),
+        }
+    }
+    pub fn string(&mut self, t: u64, signal: usize, value: &str) -> io::Result<()> {
+        self.at(t)?;
+        writeln!(self.out, "s{} {}", escape(value), self.signals[signal].id)
+    }
+
+    // Mark the end of the capture, so the last sample has a duration.
+    pub fn finish(&mut self, t: u64) -> io::Result<()> {
+        self.at(t)?;
+        self.out.flush()
+    }
+}
+
+// String values cannot contain whitespace.
+pub fn escape(s: &str) -> String {
+    let mut rv = String::new();
+    for c in s.chars() {
+        if c.is_ascii_graphic() && c != '\\' { rv.push(c); }
+        else { rv.push_str(&format!("\\x{:02x}", c as u32)); }
+    }
+    rv
+}
+
+
+// Time unit in femtoseconds, e.g. "1ns" or "10 us".
+fn parse_timescale(spec: &str) -> Option<u128> {
+    let split = spec.find(|c: char| !c.is_ascii_digit())?;
+    let (n, unit) = spec.split_at(split);
+    let n: u128 = n.parse().ok()?;
+    let fs = match unit.trim() {
+        "s"  => 1_000_000_000_000_000,
+        "ms" => 1_000_000_000_000,
+        "us" => 1_000_000_000,
+        "ns" => 1_000_000,
+        "ps" => 1_000,
+        "fs" => 1,
+        _ => return None,
+    };
+    Some(n * fs)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Bits of a VCD variable that are mapped to the bus.
+struct Var {
+    channel: usize,  // bus channel of bit 0
+    width: usize,
+}
+
+pub struct Reader<R: BufRead> {
+    input: R,
+    tokens: VecDeque<String>,
+    vars: HashMap<String, Vec<Var>>,
+    names: Vec<String>,
+    unit: u128,          // fs per VCD time unit
+    samplerate: u128,
+    bus: usize,
+    next: Option<u128>,  // next timestamp in the file, in fs
+    last: u128,          // last timestamp that was applied
+    sample: u128,        // index of the next output sample
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> Reader<R> {
+    // Parse the header and select wires by name, or by full dotted
+    // name including scopes.  Wires are assigned to bus channels in
+    // the order given, with vectors taking one channel per bit.  An
+    // empty selection takes all variables in file order.
+    pub fn new(input: R, wires: &[String], samplerate: usize) -> io::Result<Reader<R>> {
+        let mut r = Reader {
+            input,
+            tokens: VecDeque::new(),
+            vars: HashMap::new(),
+            names: vec![],
+            unit: 1_000_000_000_000_000,
+            samplerate: samplerate.max(1) as u128,
+            bus: 0,
+            next: None,
+            last: 0,
+            sample: 0,
+            error: None,
+        };
+        // (id, width, name, full name) in declaration order
+        let mut decls = vec![];
+        let mut scope: Vec<String> = vec![];
+        loop {
+            let token = match r.token()? {
+                Some(token) => token,
+                None => return Err(invalid("vcd: no $enddefinitions".to_string())),
+            };
+            match &token[..] {
+                "$timescale" => {
+                    let spec = r.until_end()?.join("");
+                    r.unit = match parse_timescale(&spec) {
+                        Some(unit) => unit,
+                        None => return Err(invalid(format!("vcd: bad timescale {}", spec))),
+                    };
+                }
+                "$scope" => {
+                    let args = r.until_end()?;
+                    scope.push(args.last().cloned().unwrap_or_default());
+                }
+                "$upscope" => {
+                    r.until_end()?;
+                    scope.pop();
+                }
+                "$var" => {
+                    let args = r.until_end()?;
+                    if args.len() < 4 {
+                        return Err(invalid(format!("vcd: bad $var {}", args.join(" "))));
+                    }
+                    let width: usize = match args[1].parse() {
+                        Ok(width) => width,
+                        Err(_) => return Err(invalid(format!("vcd: bad width {}", args[1]))),
+                    };
+                    let mut full = scope.clone();
+                    full.push(args[3].clone());
+                    decls.push((args[2].clone(), width, args[3].clone(), full.join(".")));
+                }
+                "$enddefinitions" => {
+                    r.until_end()?;
+                    break;
+                }
+                _ => { r.until_end()?; }
+            }
+        }
+        let selected: Vec<usize> = if wires.is_empty() {
+            (0..decls.len()).collect()
+        }
+        else {
+            let mut selected = vec![];
+            for wire in wires {
+                match decls.iter().position(|d| &d.2 == wire || &d.3 == wire) {
+                    Some(i) => selected.push(i),
+                    None => return Err(invalid(format!("vcd: no wire {}", wire))),
+                }
+            }
+            selected
+        };
+        let mut channel = 0;
+        for i in selected {
+            let (ref id, width, _, ref full) = decls[i];
+            r.vars.entry(id.clone()).or_default().push(Var { channel, width });
+            r.names.push(full.clone());
+            channel += width;
+        }
+        if channel > usize::BITS as usize {
+            return Err(invalid(format!("vcd: {} channels selected", channel)));
+        }
+        // Changes before the first timestamp are initial values.
+        r.next = r.advance()?;
+        Ok(r)
+    }
+
+    // Full names of the selected wires, in channel order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // Error that ended iteration early, if any.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn token(&mut self) -> io::Result<Option<String>> {
+        while self.tokens.is_empty() {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.tokens.extend(line.split_whitespace().map(|t| t.to_string()));
+        }
+        Ok(self.tokens.pop_front())
+    }
+
+    fn until_end(&mut self) -> io::Result<Vec<String>> {
+        let mut rv = vec![];
+        loop {
+            match self.token()? {
+                Some(ref t) if t == "$end" => return Ok(rv),
+                Some(t) => rv.push(t),
+                None => return Err(invalid("vcd: missing $end".to_string())),
+            }
+        }
+    }
+
+    fn set(&mut self, id: &str, value: &str) {
+        if let Some(vars) = self.vars.get(id) {
+            // Vectors can be shorter than the width; the rest is 0.
+            // Unknown and high impedance values read as 0.
+            let bits: Vec<u8> = value.bytes().rev().collect();
+            for var in vars {
+                for b in 0..var.width {
+                    let mask = 1 << (var.channel + b);
+                    match bits.get(b) {
+                        Some(b'1') => self.bus |= mask,
+                        _ => self.bus &= !mask,
+                    }
+                }
+            }
+        }
+    }
+
+    // Apply value changes up to the next timestamp.  Returns the
+    // timestamp, or None at end of file.
+    fn advance(&mut self) -> io::Result<Option<u128>> {
+        loop {
+            let token = match self.token()? {
+                Some(token) => token,
+                None => return Ok(None),
+            };
+            match token.as_bytes()[0] {
+                b'#' => match token[1..].parse::<u128>() {
+                    Ok(t) => return Ok(Some(t * self.unit)),
+                    Err(_) => return Err(invalid(format!("vcd: bad time {}", token))),
+                },
+                b'0' | b'1' | b'x' | b'X' | b'z' | b'Z' => {
+                    self.set(&token[1..], &token[..1]);
+                }
+                b'b' | b'B' => {
+                    let id = match self.token()? {
+                        Some(id) => id,
+                        None => return Err(invalid("vcd: truncated".to_string())),
+                    };
+                    self.set(&id, &token[1..]);
+                }
+                b'r' | b'R' | b's' | b'S' => {
+                    self.token()?;
+                }
+                b'$' => match &token[..] {
+                    // Value changes in these sections are applied.
+                    "$dumpvars" | "$dumpall" | "$dumpon" | "$dumpoff" | "$end" => (),
+                    _ => { self.until_end()?; }
+                },
+                _ => return Err(invalid(format!("vcd: bad token {}", token))),
+            }
+        }
+    }
+}
+
+// Bus value at each sample time, up to the last timestamp in the file.
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = usize;
+    fn next(&mut self) -> Option<usize> {
+        let t = self.sample * 1_000_000_000_000_000 / self.samplerate;
+        while let Some(next) = self.next {
+            if next > t { break; }
+            self.last = next;
+            match self.advance() {
+                Ok(next) => self.next = next,
+                Err(e) => { self.error = Some(e); return None; }
+            }
+        }
+        if self.next.is_none() && t > self.last {
+            return None;
+        }
+        self.sample += 1;
+        Some(self.bus)
+    }
+}
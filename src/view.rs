@@ -0,0 +1,201 @@
+/* view: Text waveforms in the terminal.
+
+Each column of the display summarizes 2^zoom samples using the mipmap
+levels: low, high, both (drawn hatched, i.e. the signal toggles inside
+the column) or neither (no data).  This gets rid of the wiggles at
+any zoom level, see doc/lars.txt "Visualize".
+
+Decoder output is shown on annotation rows below the channels, at the
+column of the sample that produced it.
+
+*/
+
+use mipmap::{self,MipMap,Store,Value};
+use decoder::Item;
+use libc;
+use std::io::{self,Read};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+
+pub struct Window {
+    pub start: usize,  // first sample
+    pub zoom: usize,   // log2 of samples per column
+    pub width: usize,  // columns for the waveform
+    pub samplerate: usize,  // for the time in the header, 0 if unknown
+}
+
+pub struct Annotation {
+    pub name: String,
+    pub items: Vec<(usize, String)>,  // sample index, text
+}
+
+fn cell(value: Value) -> char {
+    match value {
+        Value::Low     => '_',
+        Value::High    => '\u{203e}',  // overline
+        Value::Both    => '\u{2592}',  // medium shade
+        Value::Neither => ' ',
+    }
+}
+
+// Bytes are shown as text when printable, everything else as hex.
+pub fn label(item: &Item) -> String {
+    match *item {
+        Item::Byte(b) if (0x21..0x7f).contains(&b) => (b as char).to_string(),
+        Item::Byte(b)       => format!("{:02x}", b),
+        Item::Word(w)       => format!("{:x}", w),
+        Item::Packet(ref p) => p.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+// Smallest zoom that shows len samples in width columns.
+pub fn fit(len: usize, width: usize) -> usize {
+    let mut zoom = 0;
+    while width.max(1) << zoom < len { zoom += 1; }
+    zoom
+}
+
+// Width of the name column.
+pub fn label_width(names: &[String], annotations: &[Annotation]) -> usize {
+    names.iter().map(|n| n.chars().count())
+        .chain(annotations.iter().map(|a| a.name.chars().count()))
+        .max().unwrap_or(0)
+}
+
+// Lines of text for one screen: a header, one line per channel and one
+// per annotation row.
+pub fn render<M: MipMap>(store: &Store<M>, names: &[String],
+                         annotations: &[Annotation], w: &Window) -> Vec<String> {
+    let scale = 1 << w.zoom;
+    let label = label_width(names, annotations);
+    let mut lines = vec![];
+    let end = w.start + w.width * scale;
+    let header = match w.samplerate {
+        0  => format!("{}..{}, {} samples/col", w.start, end, scale),
+        sr => format!("{}..{}, {} samples/col, {:.6} s", w.start, end, scale,
+                      w.start as f64 / sr as f64),
+    };
+    lines.push(format!("{:1$} {2}", "", label, header));
+
+    // The mipmap is queried once per column, for all channels.
+    let columns: Vec<(M, M)> = (0..w.width)
+        .map(|i| store.range(w.start + i * scale, w.start + (i + 1) * scale))
+        .collect();
+    for (c, name) in names.iter().enumerate() {
+        let wave: String = columns.iter().map(|&p| cell(mipmap::value(p, c))).collect();
+        lines.push(format!("{:>1$} {2}", name, label, wave));
+    }
+
+    for a in annotations {
+        let mut row = vec![' '; w.width];
+        let mut free = 0;  // first column not yet written
+        for (t, text) in a.items.iter() {
+            if *t < w.start || *t >= end { continue; }
+            let col = (t - w.start) / scale;
+            if col < free { continue; }
+            for (i, ch) in text.chars().enumerate() {
+                if col + i >= w.width { break; }
+                row[col + i] = ch;
+            }
+            // Keep one column of space between items.
+            free = col + text.chars().count() + 1;
+        }
+        let row: String = row.into_iter().collect();
+        lines.push(format!("{:>1$} {2}", a.name, label, row));
+    }
+    lines
+}
+
+// Key bindings for the interactive viewer.
+pub enum Key {
+    Left, Right, ZoomIn, ZoomOut, Home, End, Quit, Other,
+}
+
+pub fn key(bytes: &[u8]) -> Key {
+    match bytes {
+        b"h" | b"\x1b[D" => Key::Left,
+        b"l" | b"\x1b[C" => Key::Right,
+        b"+" | b"i" | b"\x1b[A" => Key::ZoomIn,
+        b"-" | b"o" | b"\x1b[B" => Key::ZoomOut,
+        b"g" => Key::Home,
+        b"G" => Key::End,
+        b"q" | b"\x03" => Key::Quit,
+        _ => Key::Other,
+    }
+}
+
+impl Window {
+    // Pan by a quarter screen, zoom around the center.
+    pub fn apply(&mut self, key: &Key, len: usize, max_zoom: usize) {
+        let scale = 1 << self.zoom;
+        let span = self.width * scale;
+        match *key {
+            Key::Left  => self.start = self.start.saturating_sub(span / 4),
+            Key::Right => self.start = (self.start + span / 4).min(len.saturating_sub(1)),
+            Key::ZoomIn if self.zoom > 0 => {
+                self.start += span / 4;
+                self.zoom -= 1;
+            },
+            Key::ZoomOut if self.zoom < max_zoom => {
+                self.start = self.start.saturating_sub(span / 2);
+                self.zoom += 1;
+            },
+            Key::Home => self.start = 0,
+            Key::End  => self.start = len.saturating_sub(span),
+            _ => (),
+        }
+    }
+}
+
+pub fn is_tty() -> bool {
+    unsafe { libc::isatty(1) == 1 }
+}
+
+/* Terminal in raw mode, restored on drop.  Keys are read from the
+controlling terminal, so samples can still come from stdin. */
+pub struct Terminal {
+    tty: File,
+    saved: libc::termios,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Terminal> {
+        let tty = File::open("/dev/tty")?;
+        let fd = tty.as_raw_fd();
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // Keep output processing, so \n still returns the carriage.
+        raw.c_oflag = saved.c_oflag;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Terminal { tty, saved })
+    }
+    // Columns and rows.
+    pub fn size(&self) -> (usize, usize) {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, &mut ws) } == 0
+            && ws.ws_col > 0 {
+            (ws.ws_col as usize, ws.ws_row as usize)
+        }
+        else {
+            (80, 24)
+        }
+    }
+    // Escape sequences arrive in a single read.
+    pub fn key(&mut self) -> io::Result<Key> {
+        let mut buf = [0; 16];
+        let n = self.tty.read(&mut buf)?;
+        Ok(key(&buf[..n]))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.saved) };
+    }
+}
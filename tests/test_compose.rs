@@ -0,0 +1,63 @@
+extern crate logan;
+use logan::sm::{apply,uart,slip,diff,Push};
+use logan::gen::{self,Encode};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+fn slip_config() -> slip::Config {
+    slip::Config { end: 0x0D, esc: 0x0C, esc_end: 0x0B, esc_esc: 0x0A }
+}
+
+// UART -> SLIP -> packet length, as a single boxed value.
+fn test_then() {
+    let c = uart::Config { period: 4, nb_bits: 8, channel: 0 };
+    let mut g = gen::uart(c);
+    g.idle = 0;
+    let bus = g.encode(&[0x0D, 1, 2, 3, 0x0C, 0x0B, 0x0D, 4, 5, 0x0D]);
+
+    let mut pipeline: Box<dyn Push<usize,usize>> =
+        Box::new(uart::init(c)
+                 .then(slip::init(slip_config()))
+                 .filter(|p: &Vec<u8>| !p.is_empty())
+                 .map(|p: Vec<u8>| p.len()));
+    let lengths: Vec<usize> = apply(&mut pipeline, bus.iter().cloned()).collect();
+    assert_eq!(lengths, vec![4, 2]);
+    println!("then OK");
+}
+
+fn test_inspect_tee() {
+    let seen = Rc::new(RefCell::new(vec![]));
+    let side = Rc::new(RefCell::new(vec![]));
+    let (seen1, side1) = (seen.clone(), side.clone());
+    let mut sm = diff::init()
+        .inspect(move |&b: &usize| seen1.borrow_mut().push(b))
+        .tee(diff::init().map(move |b: usize| side1.borrow_mut().push(b)));
+    let out: Vec<usize> = apply(&mut sm, [0,1,1,2,2,2,3].iter()).collect();
+    assert_eq!(out, vec![1,2,3]);
+    assert_eq!(*seen.borrow(), vec![1,2,3]);
+    assert_eq!(*side.borrow(), vec![1,2,3]);
+    println!("inspect/tee OK");
+}
+
+fn test_stamp() {
+    let mut sm = diff::init().stamp();
+    let out: Vec<(usize,usize)> = apply(&mut sm, [0,1,1,2,2,2,3].iter()).collect();
+    assert_eq!(out, vec![(1,1),(3,2),(6,3)]);
+    sm.reset();
+    let mut sm = sm.starting_at(100);
+    assert_eq!(sm.push(&5), Some((100,5)));
+    assert_eq!(sm.push(&5), None);
+    assert_eq!(sm.push(&6), Some((102,6)));
+    println!("stamp OK");
+}
+
+fn main() {
+    test_then();
+    test_inspect_tee();
+    test_stamp();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,92 @@
+extern crate logan;
+use logan::control;
+use logan::sm::uart;
+use logan::gen::{self,Encode};
+use logan::decoder::{Registry,Options,Item};
+use std::io::{BufRead,BufReader,Write};
+use std::os::unix::net::UnixStream;
+
+// 8N1 at 4 samples per bit, on a given channel.
+fn generator(channel: usize) -> gen::Uart {
+    let mut g = gen::uart(uart::Config { period: 4, nb_bits: 8, channel });
+    g.idle = 0;
+    g
+}
+
+fn run(session: &mut control::Session, bus: &[usize]) -> Vec<(usize, Item)> {
+    let mut out = vec![];
+    for &b in bus {
+        session.push(b, &mut |slot, _t, item| { out.push((slot, item)); Ok(()) }).unwrap();
+    }
+    out
+}
+
+fn session_options() -> Options {
+    let mut options = Options::new();
+    options.set("samplerate", "4");
+    options.set("baudrate", "1");
+    options
+}
+
+fn test_session() {
+    let registry = Registry::builtin();
+    let names = vec!["uart".to_string(), "diff".to_string()];
+    let mut s = control::Session::new(&registry, &names, &session_options()).unwrap();
+    assert_eq!(s.command("disable diff"), "ok\n");
+    let out = run(&mut s, &generator(0).encode(&[0x41, 0x42]));
+    assert!(out.iter().all(|o| o.0 == 0));
+    assert_eq!(out.len(), 2);
+
+    // Move the UART to channel 1
+    assert_eq!(s.command("set uart channel=1"), "ok\n");
+    let out = run(&mut s, &generator(1).encode(&[0x43]));
+    assert_eq!(out.len(), 1);
+    assert!(matches!(out[0].1, Item::Byte(0x43)));
+
+    assert_eq!(s.command("enable diff"), "ok\n");
+    assert_eq!(s.command("reset"), "ok\n");
+    assert_eq!(s.command("stats"), "samples 120\nuart enabled items 3\ndiff enabled items 0\nok\n");
+    assert_eq!(s.command("disable spi"), "error: no decoder spi\n");
+    assert_eq!(s.command("set uart baudrate=fast"), "error: option 'baudrate': invalid digit found in string\n");
+    assert_eq!(s.command("frobnicate"), "error: unknown command frobnicate\n");
+    assert_eq!(s.command("set"), "error: set: missing argument\n");
+    assert!(control::parse("").is_err());
+    println!("control session OK");
+}
+
+fn test_socket() {
+    let path = std::env::temp_dir().join(format!("logan-test-{}.sock", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let registry = Registry::builtin();
+    let mut s = control::Session::new(&registry, &["diff".to_string()], &Options::new()).unwrap();
+    let requests = control::listen(&path).unwrap();
+    let mut stream = UnixStream::connect(&path).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    stream.write_all(b"disable diff\n").unwrap();
+    // The request is picked up by polling, as in the sample loop.
+    let req = requests.recv().unwrap();
+    req.reply.send(s.command(&req.line)).unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "ok\n");
+    assert!(!s.slots[0].enabled);
+    let _ = std::fs::remove_file(&path);
+    println!("control socket OK");
+}
+
+fn test_fd() {
+    for fd in 0..3 { assert!(control::from_fd(fd).is_err()); }
+    assert!(control::from_fd(1000).is_err());
+    println!("control fd OK");
+}
+
+fn main() {
+    test_session();
+    test_socket();
+    test_fd();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
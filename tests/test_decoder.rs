@@ -0,0 +1,64 @@
+extern crate logan;
+use logan::sm::{apply,Resync};
+use logan::decoder::{Registry,Options,Item};
+
+fn test_options() {
+    let args = ["channel=3", "--bits=7", "--baudrate", "0x100"];
+    let o = Options::parse(args.iter().map(|s| s.to_string())).unwrap();
+    assert_eq!(o.usize("channel", 0).unwrap(), 3);
+    assert_eq!(o.usize("bits", 8).unwrap(), 7);
+    assert_eq!(o.usize("baudrate", 0).unwrap(), 256);
+    assert_eq!(o.usize("nonexistent_option", 42).unwrap(), 42);
+    assert!(Options::parse(vec!["channel".to_string()]).is_err());
+    assert!(Options::parse(vec!["--channel".to_string()]).is_err());
+    println!("options OK");
+}
+
+fn test_registry() {
+    let r = Registry::builtin();
+    assert!(r.create("nonexistent", &Options::new()).is_err());
+    for e in r.entries() {
+        assert!(r.create(e.name, &Options::new()).is_ok());
+    }
+
+    // UART at 4 samples per bit.
+    let mut o = Options::new();
+    o.set("samplerate", "400");
+    o.set("baudrate", "100");
+    let mut uart = r.create("uart", &o).unwrap();
+    let bus: Vec<usize> = [0x41usize, 0x42].iter()
+        .flat_map(|&b| (0..10).map(move |i| (((b | 0x100) << 1) >> i) & 1))
+        .flat_map(|bit| (0..4).map(move |_| bit))
+        .collect();
+    let out: Vec<Item> = apply(&mut uart, bus.iter().cloned()).collect();
+    assert_eq!(out, vec![Item::Byte(0x41), Item::Byte(0x42)]);
+    // Resync points in samples as usize and in byte captures.
+    let bytes: Vec<u8> = bus.iter().map(|&b| b as u8).collect();
+    let idle: Vec<usize> = vec![1; 50];
+    assert_eq!(uart.resync(&idle, 0), Some(43));
+    assert_eq!(uart.resync(&bus, 0), None);
+    assert_eq!(uart.resync(&bytes, 0), None);
+    assert_eq!(r.create("diff", &o).unwrap().resync(&idle, 0), None);
+
+    o.set("baudrate", "1000");
+    assert!(r.create("uart", &o).is_err());
+    // Channels are bits of the bus sample.
+    o.set("baudrate", "100");
+    o.set("channel", "63");
+    assert!(r.create("uart", &o).is_ok());
+    o.set("channel", "64");
+    assert!(r.create("uart", &o).is_err());
+    o.set("clock", "70");
+    assert!(r.create("spi", &o).is_err());
+    println!("registry OK");
+}
+
+fn main() {
+    test_options();
+    test_registry();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
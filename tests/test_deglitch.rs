@@ -0,0 +1,55 @@
+extern crate logan;
+use logan::sm::{apply,uart,deglitch};
+use logan::gen::{self,Encode};
+
+// Pulses shorter than the width are removed, longer ones are passed
+// with a delay of width - 1 samples.
+fn test_pulses() {
+    let mut d = deglitch::init(deglitch::Config { widths: vec![3, 0] });
+    let ch0 = [0,0,1,0,0,1,1,0,0,1,1,1,0,0,0,1,0,0,0,0];
+    let ch1 = [1,0,1,0,1,0,1,0,1,0,1,0,1,0,1,0,1,0,1,0];
+    let bus: Vec<usize> = ch0.iter().zip(ch1.iter()).map(|(a,b)| a | (b << 1)).collect();
+    let out: Vec<usize> = apply(&mut d, bus.iter()).collect();
+    assert_eq!(out.len(), bus.len());
+    let out0: Vec<usize> = out.iter().map(|b| b & 1).collect();
+    let out1: Vec<usize> = out.iter().map(|b| b >> 1).collect();
+    assert_eq!(out0, [0,0,0,0,0,0,0,0,0,1,1,1,0,0,0,0,0,0,0,0]);
+    assert_eq!(out1, ch1);
+    assert_eq!(d.glitches(), &[3, 0]);
+    d.reset();
+    assert_eq!(d.glitches(), &[0, 0]);
+    println!("deglitch pulses OK");
+}
+
+// UART with single sample spikes in idle and data.
+fn test_uart() {
+    let period = 8;
+    let c = uart::Config { period, nb_bits: 8, channel: 0 };
+    let data_in: Vec<usize> = (0..256).collect();
+    // frames with an idle bit in between
+    let clean = gen::uart(c).encode(&data_in);
+    let noisy: Vec<usize> = clean.iter().enumerate()
+        .map(|(i, &b)| if i % 37 == 5 { b ^ 1 } else { b })
+        .collect();
+
+    let mut u = uart::init(c);
+    let out: Vec<usize> = apply(&mut u, noisy.iter()).collect();
+    assert!(out != data_in);
+
+    let mut d = deglitch::init(deglitch::Config { widths: vec![2] });
+    let mut u = uart::init(c);
+    let out: Vec<usize> = apply(&mut u, apply(&mut d, noisy.iter())).collect();
+    assert_eq!(out, data_in);
+    assert!(d.glitches()[0] > 0);
+    println!("deglitch uart OK ({})", d.report());
+}
+
+fn main() {
+    test_pulses();
+    test_uart();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,70 @@
+extern crate logan;
+use logan::erl::{self,Msg};
+use logan::output::{self,Format};
+use logan::decoder::Item;
+
+fn test_etf() {
+    // Compare with term_to_binary/1
+    assert_eq!(erl::etf(&Msg::Word(65, 1)),
+               b"\x83\x68\x02\x77\x04word\x61\x41".to_vec());
+    assert_eq!(erl::etf(&Msg::Time(300)),
+               b"\x83\x68\x02\x77\x04time\x62\x00\x00\x01\x2c".to_vec());
+    assert_eq!(erl::etf(&Msg::Word(1 << 40, 8)),
+               b"\x83\x68\x02\x77\x04word\x6e\x06\x00\x00\x00\x00\x00\x00\x01".to_vec());
+    assert_eq!(erl::etf(&Msg::Packet(&[1, 2])),
+               b"\x83\x68\x02\x77\x06packet\x6d\x00\x00\x00\x02\x01\x02".to_vec());
+    println!("erl etf OK");
+}
+
+fn test_tagged() {
+    assert_eq!(erl::tagged(&Msg::Word(0x1234, 2)), vec![1, 0x12, 0x34]);
+    assert_eq!(erl::tagged(&Msg::Status("eof")), b"\x05eof".to_vec());
+    assert_eq!(erl::tagged(&Msg::Time(1)), vec![4, 0, 0, 0, 0, 0, 0, 0, 1]);
+    let mut out = vec![];
+    erl::frame(&mut out, &[9, 8, 7]).unwrap();
+    assert_eq!(out, vec![0, 0, 0, 3, 9, 8, 7]);
+    println!("erl tagged OK");
+}
+
+// Items are preceded by a time message when the time changes.
+fn test_output() {
+    let mut out = vec![];
+    {
+        let mut w = output::init(output::Config {
+            format: Format::Erl(false),
+            decoder: "uart".to_string(),
+            samplerate: 1000,
+            word_bytes: 1,
+        }, &mut out).unwrap();
+        w.status("start").unwrap();
+        w.item(7, &Item::Byte(0x41)).unwrap();
+        w.item(7, &Item::Byte(0x42)).unwrap();
+        w.error("oops").unwrap();
+    }
+    let mut frames = vec![];
+    let mut rest = &out[..];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        frames.push(rest[4..4+len].to_vec());
+        rest = &rest[4+len..];
+    }
+    assert_eq!(frames, vec![
+        b"\x05start".to_vec(),
+        vec![4, 0, 0, 0, 0, 0, 0, 0, 7],
+        vec![1, 0x41],
+        vec![1, 0x42],
+        b"\x03oops".to_vec(),
+    ]);
+    println!("erl output OK");
+}
+
+fn main() {
+    test_etf();
+    test_tagged();
+    test_output();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,139 @@
+extern crate logan;
+use logan::sm::{apply,uart,syncser,slip,Push};
+use logan::decoder::{Registry,Options,Item};
+use logan::gen::{self,Encode};
+
+fn options(args: &[&str]) -> Options {
+    Options::parse(args.iter().map(|a| a.to_string())).unwrap()
+}
+
+fn test_uart() {
+    let c = uart::Config { period: 3, nb_bits: 8, channel: 2 };
+    let mut g = gen::uart(c);
+    assert_eq!(g.bits(0x01), vec![1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+    g.parity = gen::Parity::Even;
+    g.stop_bits = 2;
+    assert_eq!(g.bits(0x07)[10..], [1, 1, 1]);
+    g.parity = gen::Parity::Odd;
+    assert_eq!(g.bits(0x07)[10..], [0, 1, 1]);
+    // Other channels are 0.
+    let bus = g.encode(&[0x00]);
+    assert_eq!(bus.len(), 13 * 3);
+    assert!(bus.iter().all(|&b| b & !4 == 0));
+    // The parity bit decodes as the MSB of a 9 bit word.
+    let data_in: Vec<usize> = (0..256).collect();
+    let c9 = uart::Config { nb_bits: 9, ..c };
+    let data_out: Vec<usize> = apply(&mut uart::init(c9), g.encode(&data_in).into_iter()).collect();
+    let parity: Vec<usize> = data_in.iter().map(|&w| w | ((w.count_ones() as usize & 1) ^ 1) << 8).collect();
+    assert_eq!(data_out, parity);
+    // Extra stop bits decode as idle.
+    g.parity = gen::Parity::None;
+    let data_out: Vec<usize> = apply(&mut uart::init(c), g.encode(&data_in).into_iter()).collect();
+    assert_eq!(data_out, data_in);
+    println!("gen uart OK");
+}
+
+fn test_spi() {
+    for mode in 0..4 {
+        for &framed in [false, true].iter() {
+            let c = syncser::Config {
+                clock_polarity: mode >> 1,
+                clock_edge: (mode >> 1) ^ (mode & 1) ^ 1,
+                frame_enable: framed,
+                frame_channel: 2,
+                ..syncser::config()
+            };
+            let data_in = vec![0x12, 0xA5, 0xFF, 0x00];
+            let bus = gen::spi(c, 2).encode(&data_in);
+            assert_eq!(bus[0] >> c.clock_channel & 1, c.clock_polarity);
+            assert_eq!(bus[bus.len()-1] >> c.clock_channel & 1, c.clock_polarity);
+            let data_out: Vec<usize> = apply(&mut syncser::init(c), bus.into_iter()).collect();
+            assert_eq!(data_out, data_in);
+        }
+    }
+    println!("gen spi OK");
+}
+
+// Bytes and acknowledge bits sampled on the rising clock edge, with
+// start and stop conditions as markers.
+fn i2c_sample(g: &gen::I2c, bus: &[usize]) -> Vec<String> {
+    let mut out = vec![];
+    let (mut scl, mut sda) = (1, 1);
+    let mut bits = vec![];
+    for &b in bus {
+        let (c, d) = (b >> g.scl & 1, b >> g.sda & 1);
+        if c == 1 && scl == 1 && d != sda {
+            bits.clear();
+            out.push(if d == 0 { "S" } else { "P" }.to_string());
+        }
+        if c == 1 && scl == 0 {
+            bits.push(d);
+            if bits.len() == 9 {
+                let byte = bits[..8].iter().fold(0, |a, &b| (a << 1) | b);
+                out.push(format!("{:02x}{}", byte, if bits[8] == 0 { "a" } else { "n" }));
+                bits.clear();
+            }
+        }
+        scl = c;
+        sda = d;
+    }
+    out
+}
+
+fn test_i2c() {
+    let g = gen::i2c(3, 1, 2);
+    let bus = g.encode(&[gen::address(0x50, false), 0x00, 0xFF]);
+    assert!(bus.iter().all(|&b| b & !0b1010 == 0));
+    assert_eq!(i2c_sample(&g, &bus), vec!["S", "a0a", "00a", "ffa", "P"]);
+    // A period of 1 still leaves SDA stable while SCL is high.
+    let g = gen::i2c(0, 1, 1);
+    let bus = g.encode(&[gen::address(0x3C, true)]);
+    assert_eq!(i2c_sample(&g, &bus), vec!["S", "79a", "P"]);
+    println!("gen i2c OK");
+}
+
+fn test_slip() {
+    let c = slip::Config { end: 0x0D, esc: 0x0C, esc_end: 0x0B, esc_esc: 0x0A };
+    assert_eq!(gen::slip_frame(&c, &[1, 0x0D, 0x0C, 2]), vec![1, 0x0C, 0x0B, 0x0C, 0x0A, 2, 0x0D]);
+    let u = uart::Config { period: 4, nb_bits: 8, channel: 0 };
+    let g = gen::slip(c, gen::uart(u));
+    let packets = vec![vec![1, 0x0D, 2], vec![0x0C, 0x0B], vec![3]];
+    let bus = g.packets(&packets);
+    let out: Vec<Vec<u8>> = apply(&mut uart::init(u).then(slip::init(c)), bus.into_iter()).collect();
+    assert_eq!(out, packets);
+    println!("gen slip OK");
+}
+
+// Generators configured like the decoders round trip through them.
+fn test_options() {
+    let r = Registry::builtin();
+    let decode = |name: &str, o: &Options, words: &[usize]| -> Vec<Item> {
+        let bus = gen::from_options(name, o).unwrap().encode(words);
+        let mut d = r.create(name, o).unwrap();
+        apply(&mut d, bus.into_iter()).collect()
+    };
+    let o = options(&["samplerate=1000000", "baudrate=100000", "channel=1", "stop=2", "idle=3"]);
+    assert_eq!(decode("uart", &o, &[0x41, 0x42]), vec![Item::Byte(0x41), Item::Byte(0x42)]);
+    assert_eq!(decode("slip", &o, &[0x41, 0x0D]), vec![Item::Packet(vec![0x41, 0x0D])]);
+    let o = options(&["samplerate=1000000", "baudrate=100000", "polarity=1", "edge=0"]);
+    assert_eq!(decode("spi", &o, &[0x5A]), vec![Item::Word(0x5A)]);
+
+    assert!(gen::from_options("uart", &options(&["parity=mark"])).is_err());
+    assert!(gen::from_options("spi", &options(&["samplerate=100", "baudrate=100"])).is_err());
+    assert!(gen::from_options("ice40", &options(&[])).is_err());
+    assert!(gen::from_options("i2c", &options(&[])).is_ok());
+    assert!(gen::from_options("uart", &options(&["channel=70"])).is_err());
+    assert!(gen::from_options("i2c", &options(&["sda=64"])).is_err());
+    println!("gen options OK");
+}
+
+fn main() {
+    test_uart();
+    test_spi();
+    test_i2c();
+    test_slip();
+    test_options();
+}
+
+#[test]
+fn run_tests() { main() }
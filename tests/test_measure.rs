@@ -0,0 +1,59 @@
+extern crate logan;
+use logan::sm::{apply,measure};
+
+// Square wave on channel 0: high for `high` samples, low for `low`.
+fn square(n: usize, high: usize, low: usize) -> Vec<usize> {
+    (0..n).map(|i| if i % (high + low) < high { 1 } else { 0 }).collect()
+}
+
+fn test_capture() {
+    // Channel 1 is constant.
+    let bus: Vec<usize> = square(100, 3, 7).iter().map(|b| b | 2).collect();
+    let mut m = measure::init(measure::Config { channels: 2, window: 0, step: 0 });
+    let reports: Vec<measure::Report> = apply(&mut m, bus.iter()).collect();
+    assert_eq!(reports.len(), 1);
+    let r = &reports[0];
+    assert_eq!((r.start, r.end), (0, 100));
+    let s = &r.channels[0];
+    // The first rising edge is at the start of the capture.
+    assert_eq!((s.rising, s.falling), (9, 10));
+    assert_eq!((s.high.count, s.high.min, s.high.max), (9, 3, 3));
+    assert_eq!((s.low.count, s.low.min, s.low.max), (9, 7, 7));
+    assert_eq!((s.period.count, s.period.mean()), (8, Some(10.0)));
+    assert_eq!(s.period.hist, vec![0, 0, 0, 8]);
+    assert_eq!(s.frequency(1000), Some(100.0));
+    assert_eq!(s.duty(), Some(0.3));
+    assert_eq!(r.channels[1], measure::Summary::default());
+    assert_eq!(r.channels[1].duty(), None);
+    let table = r.table(1000);
+    assert!(table.contains("100.000Hz"));
+    assert!(table.contains("30.0%"));
+    println!("measure capture OK");
+}
+
+fn test_window() {
+    // Frequency doubles halfway.
+    let mut bus = square(200, 5, 5);
+    bus.extend(square(200, 2, 3));
+    let mut m = measure::init(measure::Config { channels: 1, window: 100, step: 50 });
+    let reports: Vec<measure::Report> = apply(&mut m, bus.iter()).collect();
+    assert_eq!(reports.len(), 8);
+    assert_eq!((reports[2].start, reports[2].end), (50, 150));
+    assert_eq!(reports[2].channels[0].period.mean(), Some(10.0));
+    assert_eq!(reports[7].channels[0].period.mean(), Some(5.0));
+    assert_eq!(reports[7].channels[0].rising, 20);
+    // Partial window at the end.
+    let mut m = measure::init(measure::Config { channels: 1, window: 100, step: 0 });
+    let reports: Vec<measure::Report> = apply(&mut m, bus[..250].iter()).collect();
+    assert_eq!(reports.len(), 3);
+    assert_eq!((reports[2].start, reports[2].end), (150, 250));
+    println!("measure window OK");
+}
+
+fn main() {
+    test_capture();
+    test_window();
+}
+
+#[test]
+fn run_tests() { main() }
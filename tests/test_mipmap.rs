@@ -0,0 +1,232 @@
+extern crate logan;
+use logan::mipmap::{self,Value};
+
+// Deterministic pseudo-random samples.
+fn samples(n: usize, seed: u32) -> Vec<u8> {
+    let mut x = seed | 1;
+    (0..n).map(|_| {
+        x ^= x << 13; x ^= x >> 17; x ^= x << 5;
+        // Mostly constant channels, so that all values occur.
+        (x & (x >> 8)) as u8
+    }).collect()
+}
+
+fn brute(raw: &[u8]) -> (u8, u8) {
+    raw.iter().fold((0, 0), |(min, max), &s| (min | !s, max | s))
+}
+
+fn test_levels() {
+    for nb_levels in 0..11 {
+        let raw = samples(1 << nb_levels, 1234 + nb_levels as u32);
+        let store = mipmap::mipmap(&raw);
+        assert_eq!(store.nb_levels(), nb_levels);
+        assert_eq!(store.len(), raw.len());
+        for level in 0..nb_levels+1 {
+            let (min, max) = store.level(level);
+            assert_eq!(min.len(), 1 << (nb_levels - level));
+            for (i, block) in raw.chunks(1 << level).enumerate() {
+                assert_eq!((min[i], max[i]), brute(block));
+            }
+        }
+    }
+    println!("mipmap levels OK");
+}
+
+fn test_range() {
+    let raw = samples(256, 99);
+    let store = mipmap::mipmap(&raw);
+    for from in 0..raw.len() {
+        for to in (from+1..raw.len()+1).step_by(7) {
+            assert_eq!(store.range(from, to), brute(&raw[from..to]));
+        }
+    }
+    assert_eq!(store.range(5, 5), (0, 0));
+    println!("mipmap range OK");
+}
+
+fn test_value() {
+    let raw: Vec<u8> = vec![0b01, 0b01, 0b11, 0b01];
+    let store = mipmap::mipmap(&raw);
+    assert_eq!(mipmap::value(store.get(0, 0), 1), Value::Low);
+    assert_eq!(mipmap::value(store.get(0, 0), 0), Value::High);
+    assert_eq!(mipmap::value(store.get(2, 0), 1), Value::Both);
+    assert_eq!(mipmap::value(store.range(2, 2), 1), Value::Neither);
+    println!("mipmap value OK");
+}
+
+fn temp(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("logan-{}-{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+fn test_file() {
+    let raw = samples(1 << 12, 7);
+    let capture = temp("capture.bin");
+    let path = temp("store.mip");
+    std::fs::write(&capture, &raw).unwrap();
+    let memory = mipmap::mipmap(&raw);
+    {
+        let store: mipmap::Store<u8> =
+            mipmap::create_from_capture(&path, &capture, 6, 24000000).unwrap();
+        assert_eq!(store.level(3), memory.level(3));
+    }
+    let store: mipmap::Store<u8> = mipmap::open(&path).unwrap();
+    assert_eq!(store.channels(), 6);
+    assert_eq!(store.width(), 8);
+    assert_eq!(store.samplerate(), 24000000);
+    assert_eq!(store.nb_levels(), 12);
+    for level in 0..13 {
+        assert_eq!(store.level(level), memory.level(level));
+    }
+    assert_eq!(store.range(100, 2000), brute(&raw[100..2000]));
+
+    // Read-only stores do not change the file.
+    let store: mipmap::Store<u8> = mipmap::open_read_only(&path).unwrap();
+    assert_eq!(store.level(12), memory.level(12));
+    let empty = temp("empty.mip");
+    drop(mipmap::create_empty::<u8>(&empty, 4, 8, 0).unwrap());
+    let mut store: mipmap::Store<u8> = mipmap::open_read_only(&empty).unwrap();
+    assert_eq!(store.append(&raw[..10]), 0);
+    assert!(store.gap(1).is_err());
+    assert!(store.is_empty());
+    let _ = std::fs::remove_file(&empty);
+
+    // Wrong sample type, not a store.
+    assert!(mipmap::open::<u16>(&path).is_err());
+    assert!(mipmap::open_read_only::<u16>(&path).is_err());
+    assert!(mipmap::open::<u8>(&capture).is_err());
+    let _ = std::fs::remove_file(&capture);
+    let _ = std::fs::remove_file(&path);
+    println!("mipmap file OK");
+}
+
+// Cells are compared against samples written so far.
+fn check_partial(store: &mipmap::Store<u8>, raw: &[u8]) {
+    for level in 0..store.nb_levels()+1 {
+        let (min, max) = store.level(level);
+        for i in 0..min.len() {
+            let from = (i << level).min(raw.len());
+            let to = ((i + 1) << level).min(raw.len());
+            assert_eq!((min[i], max[i]), brute(&raw[from..to]));
+        }
+    }
+}
+
+fn test_append() {
+    let raw = samples(1000, 5);
+    let mut store = mipmap::with_capacity(10);
+    let mut n = 0;
+    for size in [1, 2, 3, 50, 0, 200, 7, 500, 300].iter() {
+        let end = (n + size).min(raw.len());
+        assert_eq!(store.append(&raw[n..end]), end - n);
+        n = end;
+        assert_eq!(store.len(), n);
+        check_partial(&store, &raw[..n]);
+    }
+    assert_eq!(store.append(&samples(100, 3)), 24);
+    assert_eq!(store.len(), 1024);
+    assert_eq!(store.append(&raw), 0);
+
+    // File-backed, reopened with its length.
+    let path = temp("live.mip");
+    {
+        let mut store: mipmap::Store<u8> = mipmap::create_empty(&path, 10, 8, 1000).unwrap();
+        store.append(&raw[..600]);
+    }
+    let mut store: mipmap::Store<u8> = mipmap::open(&path).unwrap();
+    assert_eq!(store.len(), 600);
+    store.append(&raw[600..]);
+    check_partial(&store, &raw);
+    let _ = std::fs::remove_file(&path);
+    println!("mipmap append OK");
+}
+
+// Readers see all levels at the same length.
+fn test_live() {
+    let raw = samples(1 << 16, 11);
+    let live = mipmap::Live::new(mipmap::with_capacity(16));
+    let reader = {
+        let live = live.clone();
+        std::thread::spawn(move || {
+            let mut reads = 0;
+            loop {
+                let store = live.read();
+                let top = store.get(store.nb_levels(), 0);
+                let (min, max) = store.level(0);
+                let bottom = (0..store.len()).fold((0, 0), |(a, b), i| (a | min[i], b | max[i]));
+                assert_eq!(top, bottom);
+                reads += 1;
+                if store.len() == store.capacity() { return reads; }
+            }
+        })
+    };
+    for block in raw.chunks(1000) {
+        live.append(block);
+    }
+    assert!(reader.join().unwrap() > 0);
+    println!("mipmap live OK");
+}
+
+// Arbitrary lengths are padded with 'neither'.
+fn test_padding() {
+    for &n in [1, 3, 5, 100, 1000, 1025].iter() {
+        let raw = samples(n, n as u32);
+        let store = mipmap::mipmap(&raw);
+        assert_eq!(store.len(), n);
+        assert!(store.capacity() >= n && store.capacity() < 2 * n.max(1));
+        check_partial(&store, &raw);
+        assert_eq!(store.range(0, store.capacity()), brute(&raw));
+        assert_eq!(mipmap::value(store.get(0, store.capacity() - 1), 0),
+                   if n == store.capacity() { mipmap::value(brute(&raw[n-1..]), 0) }
+                   else { Value::Neither });
+    }
+    println!("mipmap padding OK");
+}
+
+fn test_segments() {
+    let (a, b, c) = (samples(100, 1), samples(30, 2), samples(7, 3));
+    let store = mipmap::concat(&[&a, &b, &c], 10);
+    assert_eq!(store.segments(), &[(0, 100), (110, 140), (150, 157)]);
+    assert_eq!(store.len(), 157);
+    let (min, max) = store.level(0);
+    for i in (100..110).chain(140..150) {
+        assert_eq!((min[i], max[i]), (0, 0));
+    }
+    assert_eq!(store.range(90, 120), brute(&[&a[90..], &b[..10]].concat()));
+
+    // Segments are kept in the file, also across reopen.
+    let path = temp("segments.mip");
+    {
+        let mut store: mipmap::Store<u8> = mipmap::create_empty(&path, 8, 8, 1000).unwrap();
+        store.append(&a);
+        store.gap(0).unwrap();
+        store.append(&b);
+        store.gap(5).unwrap();
+    }
+    {
+        let mut store: mipmap::Store<u8> = mipmap::open(&path).unwrap();
+        assert_eq!(store.segments(), &[(0, 100), (100, 130)]);
+        assert_eq!(store.len(), 135);
+        store.append(&c);
+    }
+    let store: mipmap::Store<u8> = mipmap::open(&path).unwrap();
+    assert_eq!(store.segments(), &[(0, 100), (100, 130), (135, 142)]);
+    let _ = std::fs::remove_file(&path);
+    println!("mipmap segments OK");
+}
+
+fn main() {
+    test_levels();
+    test_range();
+    test_value();
+    test_file();
+    test_append();
+    test_live();
+    test_padding();
+    test_segments();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
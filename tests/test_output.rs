@@ -0,0 +1,55 @@
+extern crate logan;
+use logan::output::{self,Format};
+use logan::decoder::Item;
+
+fn render(format: Format, items: &[(usize, Item)]) -> Vec<u8> {
+    let mut out = vec![];
+    {
+        let mut w = output::init(output::Config {
+            format,
+            decoder: "uart".to_string(),
+            samplerate: 1000,
+            word_bytes: 2,
+        }, &mut out).unwrap();
+        for (t, item) in items {
+            w.item(*t, item).unwrap();
+        }
+    }
+    out
+}
+
+fn test_formats() {
+    let items = vec![
+        (10, Item::Byte(0x41)),
+        (20, Item::Word(0x1234)),
+        (1500, Item::Packet(vec![1, 0xab])),
+    ];
+    assert_eq!(render(Format::Raw, &items), vec![0x41, 0x34, 0x12, 1, 0xab]);
+    assert_eq!(String::from_utf8(render(Format::Native, &items)).unwrap(),
+               "A1234\n(2) - 01 ab\n");
+    assert_eq!(String::from_utf8(render(Format::Hex, &items)).unwrap(),
+               "41\n1234\n01 ab\n");
+    assert_eq!(String::from_utf8(render(Format::Csv, &items)).unwrap(), "\
+sample,time,decoder,type,payload
+10,0.010000000,uart,byte,41
+20,0.020000000,uart,word,1234
+1500,1.500000000,uart,packet,01ab
+");
+    assert_eq!(String::from_utf8(render(Format::Jsonl, &items)).unwrap(), "\
+{\"decoder\":\"uart\",\"type\":\"byte\",\"sample\":10,\"timestamp\":0.010000000,\"payload\":65}
+{\"decoder\":\"uart\",\"type\":\"word\",\"sample\":20,\"timestamp\":0.020000000,\"payload\":4660}
+{\"decoder\":\"uart\",\"type\":\"packet\",\"sample\":1500,\"timestamp\":1.500000000,\"payload\":[1,171]}
+");
+    assert_eq!(Format::parse("jsonl"), Some(Format::Jsonl));
+    assert_eq!(Format::parse("xml"), None);
+    println!("output formats OK");
+}
+
+fn main() {
+    test_formats();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
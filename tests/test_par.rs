@@ -0,0 +1,88 @@
+extern crate logan;
+use logan::sm::{apply,uart,syncser,Push};
+use logan::par;
+use logan::gen::{self,Encode};
+
+// UART byte sequence with idle gaps of varying length, so that only
+// some of the gaps are long enough to resync.
+fn uart_seq(c: uart::Config, data_in: &[usize]) -> Vec<u8> {
+    let mut g = gen::uart(c);
+    let mut bus = vec![];
+    for (n, &data) in data_in.iter().enumerate() {
+        g.idle = (n % 5) * c.nb_bits;
+        bus.extend(g.encode(&[data]).into_iter().map(|b| b as u8));
+    }
+    bus
+}
+
+fn test_uart() {
+    for period in 1..10 {
+        let c = uart::Config { period, nb_bits: 8, channel: 1 };
+        let data_in: Vec<usize> = (0..256).rev().collect();
+        let capture = uart_seq(c, &data_in);
+        let sequential: Vec<usize> =
+            apply(&mut uart::init(c), capture.iter()).collect();
+        assert_eq!(sequential, data_in);
+        assert!(par::splits(&uart::init(c), &capture, 8).len() > 2);
+        let stamps: Vec<(usize,usize)> =
+            apply(&mut uart::init(c).stamp(), capture.iter()).collect();
+        for nb_chunks in 1..9 {
+            let chunked = par::chunked(&capture, nb_chunks, || uart::init(c));
+            assert_eq!(chunked, data_in);
+            let stamped = par::stamped(&capture, nb_chunks, || uart::init(c));
+            assert_eq!(stamped, stamps);
+        }
+    }
+    println!("par uart OK");
+}
+
+fn test_syncser() {
+    let mut c = syncser::config();
+    c.data_channel = 0;
+    c.clock_channel = 1;
+    c.frame_channel = 2;
+    c.frame_enable = true;
+    let data_in: Vec<usize> = (0..256).collect();
+    let mut capture = vec![];
+    for &data in data_in.iter() {
+        for shift in 0..8 {
+            let bit = (data >> (7 - shift)) & 1;
+            for clock in 0..2 { capture.push((clock << 1) | bit); }
+        }
+        capture.push(1 << 2);
+    }
+    let sequential: Vec<usize> =
+        apply(&mut syncser::init(c), capture.iter()).collect();
+    assert_eq!(sequential, data_in);
+    assert_eq!(par::splits(&syncser::init(c), &capture, 8).len(), 9);
+    for nb_chunks in 1..9 {
+        let chunked = par::chunked(&capture, nb_chunks, || syncser::init(c));
+        assert_eq!(chunked, data_in);
+    }
+    println!("par syncser OK");
+}
+
+type Job = Box<dyn FnOnce(&[u8]) -> usize + Send>;
+
+fn test_fanout() {
+    let capture: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+    let jobs: Vec<Job> = vec![
+        Box::new(|c| c.len()),
+        Box::new(|c| c.iter().map(|&b| b as usize).sum()),
+    ];
+    let results = par::fanout(&capture, jobs);
+    assert_eq!(results[0], 1000);
+    assert_eq!(results[1], capture.iter().map(|&b| b as usize).sum());
+    println!("par fanout OK");
+}
+
+fn main() {
+    test_uart();
+    test_syncser();
+    test_fanout();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
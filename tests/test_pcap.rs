@@ -0,0 +1,86 @@
+extern crate logan;
+use logan::pcap;
+use logan::output::{self,Format};
+use logan::decoder::Item;
+
+fn u32_at(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([b[i], b[i+1], b[i+2], b[i+3]])
+}
+
+// Split a PCAPNG file into (type, body) blocks, checking lengths.
+fn blocks(b: &[u8]) -> Vec<(u32, Vec<u8>)> {
+    let mut rv = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        let len = u32_at(b, i + 4) as usize;
+        assert_eq!(len % 4, 0);
+        assert_eq!(u32_at(b, i + len - 4) as usize, len);
+        rv.push((u32_at(b, i), b[i+8..i+len-4].to_vec()));
+        i += len;
+    }
+    assert_eq!(i, b.len());
+    rv
+}
+
+fn test_linktype() {
+    assert_eq!(pcap::parse_linktype("raw"), Some(101));
+    assert_eq!(pcap::parse_linktype("user3"), Some(150));
+    assert_eq!(pcap::parse_linktype("user16"), None);
+    assert_eq!(pcap::parse_linktype("147"), Some(147));
+    assert_eq!(pcap::parse_linktype("slip"), None);
+    println!("pcap linktype OK");
+}
+
+fn write(items: &[(usize, Item)]) -> Vec<u8> {
+    let mut out = vec![];
+    {
+        let mut w = output::init(output::Config {
+            format: Format::Pcap(pcap::LINKTYPE_USER0),
+            decoder: "slip".to_string(),
+            samplerate: 2000000,
+            word_bytes: 2,
+        }, &mut out).unwrap();
+        for (t, item) in items {
+            w.item(*t, item).unwrap();
+        }
+    }
+    out
+}
+
+fn test_writer() {
+    let b = blocks(&write(&[
+        (3, Item::Packet(vec![1, 2, 3])),
+        (10000000000, Item::Packet(vec![0x45, 0, 0, 20, 9])),
+        (4, Item::Word(0x1234)),
+    ]));
+    assert_eq!(b.len(), 5);
+    assert_eq!(b[0].0, 0x0A0D0D0A);
+    assert_eq!(u32_at(&b[0].1, 0), 0x1A2B3C4D);
+    assert_eq!(b[1].0, 1);
+    assert_eq!(&b[1].1[..2], &[147, 0]);
+    for (n, &(ns, len)) in [(1500u64, 3usize), (5000000000000, 5), (2000, 2)].iter().enumerate() {
+        let (typ, ref epb) = b[2 + n];
+        assert_eq!(typ, 6);
+        let ts = ((u32_at(epb, 4) as u64) << 32) | u32_at(epb, 8) as u64;
+        assert_eq!(ts, ns);
+        assert_eq!(u32_at(epb, 12) as usize, len);
+        assert_eq!(u32_at(epb, 16) as usize, len);
+    }
+    assert_eq!(&b[3].1[20..25], &[0x45, 0, 0, 20, 9]);
+    assert_eq!(&b[4].1[20..22], &[0x34, 0x12]);
+    // Without items, the file still has its header.
+    let b = blocks(&write(&[]));
+    assert_eq!(b.len(), 2);
+    assert_eq!(b[0].0, 0x0A0D0D0A);
+    println!("pcap writer OK");
+}
+
+fn main() {
+    test_linktype();
+    test_writer();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
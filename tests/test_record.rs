@@ -0,0 +1,134 @@
+extern crate logan;
+use logan::sm::apply;
+use logan::decoder::{Registry,Options};
+use logan::{trigger,record,sigrok};
+use std::fs;
+
+fn temp(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("logan-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&path);
+    path.to_str().unwrap().to_string()
+}
+
+fn config(dir: &str, format: record::Format) -> record::Config {
+    record::Config {
+        prefix: format!("{}/ev", dir),
+        format,
+        samplerate: 1000,
+        names: vec!["a".to_string(), "b".to_string()],
+        max_samples: 0,
+        max_files: 0,
+        quota: 0,
+    }
+}
+
+fn names(r: &record::Recorder) -> Vec<String> {
+    r.files().iter().map(|p| p.file_name().unwrap().to_string_lossy().to_string()).collect()
+}
+
+// Rising edges on channel 0 at 10, 30 and 50, recorded with 2 samples
+// before and 3 after.
+fn test_gate() {
+    let dir = temp("record-gate");
+    let mut bus: Vec<usize> = (0..60).map(|i| (i as usize) << 1 & 2).collect();
+    for &t in [10, 30, 50].iter() { bus[t] |= 1; }
+    let registry = Registry::builtin();
+    let t = trigger::parse("R", &registry, &Options::new()).unwrap();
+    let mut gate = trigger::gate(t, trigger::Config { pre: 2, post: 3, count: 0 });
+    let mut r = record::init(config(&dir, record::Format::Raw)).unwrap();
+    let mut written = vec![];
+    for (t, b) in apply(&mut gate, bus.iter()) {
+        if let Some(path) = r.push(t, b).unwrap() { written.push(path); }
+    }
+    written.extend(r.flush().unwrap());
+    assert_eq!(written.len(), 3);
+    assert_eq!(names(&r), vec!["ev-000000-8.bin", "ev-000001-28.bin", "ev-000002-48.bin"]);
+    let data = fs::read(&written[1]).unwrap();
+    let expected: Vec<u8> = bus[28..34].iter().map(|&b| b as u8).collect();
+    assert_eq!(data, expected);
+    println!("record gate OK");
+}
+
+fn segments(r: &mut record::Recorder, n: usize, len: usize) {
+    for i in 0..n {
+        for t in 0..len { r.push(100 * i + t, t).unwrap(); }
+    }
+    r.flush().unwrap();
+}
+
+fn test_rotation() {
+    let dir = temp("record-rotation");
+    let mut c = config(&dir, record::Format::Raw);
+    c.max_files = 3;
+    let mut r = record::init(c.clone()).unwrap();
+    segments(&mut r, 5, 10);
+    assert_eq!(names(&r), vec!["ev-000002-200.bin", "ev-000003-300.bin", "ev-000004-400.bin"]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    // A restart picks up the existing files.
+    let mut r = record::init(c).unwrap();
+    assert_eq!(r.files().len(), 3);
+    segments(&mut r, 1, 10);
+    assert_eq!(names(&r)[2], "ev-000005-0.bin");
+    println!("record rotation OK");
+}
+
+fn test_quota() {
+    let dir = temp("record-quota");
+    let mut c = config(&dir, record::Format::Raw);
+    c.quota = 35;
+    c.max_samples = 10;
+    let mut r = record::init(c).unwrap();
+    // Segments longer than max_samples are split.
+    segments(&mut r, 2, 25);
+    assert_eq!(r.size(), 30);
+    assert_eq!(names(&r), vec!["ev-000002-20.bin", "ev-000003-100.bin",
+                             "ev-000004-110.bin", "ev-000005-120.bin"]);
+    // The newest file is kept even if it is over quota.
+    let mut c = config(&dir, record::Format::Raw);
+    c.quota = 1;
+    let r = record::init(c).unwrap();
+    assert_eq!(names(&r), vec!["ev-000005-120.bin"]);
+    assert_eq!(record::parse_size("500M"), Some(500 << 20));
+    assert_eq!(record::parse_size("2k"), Some(2048));
+    assert_eq!(record::parse_size("x"), None);
+    assert_eq!(record::parse_size("18446744073709551615G"), None);
+    // Raw files have one byte per sample.
+    let mut c = config(&dir, record::Format::Raw);
+    c.names = (0..9).map(|i| format!("D{}", i)).collect();
+    assert!(record::init(c).is_err());
+    let mut c = config(&dir, record::Format::Vcd);
+    c.names = (0..65).map(|i| format!("D{}", i)).collect();
+    assert!(record::init(c).is_err());
+    println!("record quota OK");
+}
+
+fn test_formats() {
+    let dir = temp("record-formats");
+    let mut r = record::init(config(&dir, record::Format::Sigrok)).unwrap();
+    for t in 0..8 { r.push(1000 + t, t & 3).unwrap(); }
+    let path = r.flush().unwrap().unwrap();
+    let session = sigrok::read(path.to_str().unwrap()).unwrap();
+    assert_eq!(session.samples().collect::<Vec<_>>(), vec![0, 1, 2, 3, 0, 1, 2, 3]);
+    assert_eq!(session.probes, vec!["a", "b"]);
+
+    let mut r = record::init(config(&dir, record::Format::Vcd)).unwrap();
+    for t in 0..4 { r.push(1000 + t, t & 3).unwrap(); }
+    let path = r.flush().unwrap().unwrap();
+    let vcd = fs::read_to_string(&path).unwrap();
+    assert!(vcd.contains("$var wire 1"));
+    // Time is the sample index in the input stream.
+    assert!(vcd.contains("\n#1000\n"));
+    // Files of other formats do not count.
+    assert_eq!(r.files().len(), 1);
+    println!("record formats OK");
+}
+
+fn main() {
+    test_gate();
+    test_rotation();
+    test_quota();
+    test_formats();
+}
+
+#[test]
+fn run_tests() { main() }
@@ -0,0 +1,47 @@
+extern crate logan;
+use logan::sm::{apply,uart,remap,Push};
+use logan::gen::{self,Encode};
+
+fn test_bits() {
+    let mut r = remap::init(remap::Config {
+        map: Some(vec![2, 0, 1]),
+        invert: 0b001,
+        mask: 0b011,
+    });
+    assert!(!r.is_identity());
+    assert!(remap::init(remap::config()).is_identity());
+    // out0 = !in2, out1 = in0, out2 = in1 masked off
+    assert_eq!(r.push(0b000usize), Some(0b001));
+    assert_eq!(r.push(0b100usize), Some(0b000));
+    assert_eq!(r.push(0b001usize), Some(0b011));
+    assert_eq!(r.push(0b010usize), Some(0b001));
+    println!("remap bits OK");
+}
+
+// Inverted UART on channel 3 decoded by an unmodified channel 0 UART.
+fn test_uart() {
+    let c = uart::Config { period: 4, nb_bits: 8, channel: 0 };
+    let data_in: Vec<usize> = (0..256).collect();
+    let mut g = gen::uart(uart::Config { channel: 3, ..c });
+    g.idle = 0;
+    let bus: Vec<usize> = g.encode(&data_in).iter().map(|b| b ^ (1 << 3)).collect();
+    let mut r = remap::init(remap::Config {
+        map: Some(vec![3]),
+        invert: 1,
+        mask: !0,
+    });
+    let mut u = uart::init(c);
+    let data_out: Vec<usize> = apply(&mut u, apply(&mut r, bus.iter())).collect();
+    assert_eq!(data_out, data_in);
+    println!("remap uart OK");
+}
+
+fn main() {
+    test_bits();
+    test_uart();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,86 @@
+extern crate logan;
+use logan::mipmap;
+use logan::render::{self,Config,Shape,Fill};
+use logan::view::Annotation;
+
+fn test_ticks() {
+    let c = Config { from: 0, to: 1000, width: 500, samplerate: 0 };
+    let ticks = render::ticks(&c);
+    assert_eq!(ticks.len(), 5);
+    assert_eq!(ticks[1], (200, "200".to_string()));
+
+    let c = Config { from: 150, to: 1000, width: 1000, samplerate: 1000000 };
+    let ticks = render::ticks(&c);
+    assert_eq!(ticks[0], (200, "200us".to_string()));
+    assert_eq!(ticks.last().unwrap(), &(900, "900us".to_string()));
+    println!("render ticks OK");
+}
+
+fn test_layout() {
+    // d0 toggles every sample, d1 is low then high.
+    let raw: Vec<u8> = (0..64).map(|i| (i & 1) | if i >= 32 { 2 } else { 0 }).collect();
+    let store = mipmap::mipmap(&raw);
+    let names = vec!["d0".to_string(), "d1".to_string()];
+    let a = Annotation { name: "x".to_string(),
+                         items: vec![(8, "ab".to_string()), (60, "cd".to_string())] };
+    let c = Config { from: 0, to: 64, width: 32, samplerate: 0 };
+    let image = render::layout(&store, &names, &[a], &c);
+    let label = 3 * render::CHAR;
+    assert_eq!(image.width, label + 32);
+    assert_eq!(image.height, 4 * render::ROW);
+
+    let rects: Vec<(usize, usize, usize, usize, Fill)> = image.shapes.iter().filter_map(|s| match *s {
+        Shape::Rect { x, y, w, h, fill } => Some((x, y, w, h, fill)),
+        _ => None,
+    }).collect();
+    // d0 is a single hatched run, d1 a low run, an edge and a high run.
+    assert!(rects.iter().any(|&(x, y, w, _, f)| (x, y, w, f) == (label, 24, 32, Fill::Hatch)));
+    assert!(rects.iter().any(|&(x, y, w, _, _)| (x, y, w) == (label, 55, 16)));
+    assert!(rects.iter().any(|&(x, y, w, h, _)| (x, y, w, h) == (label + 16, 44, 1, 12)));
+    assert!(rects.iter().any(|&(x, y, w, _, _)| (x, y, w) == (label + 16, 44, 16)));
+    // Text is clipped at the right edge.
+    let texts: Vec<&Shape> = image.shapes.iter().filter(|s| match **s {
+        Shape::Text { y, .. } => y == 65,
+        _ => false,
+    }).collect();
+    assert_eq!(texts, vec![&Shape::Text { x: 0, y: 65, text: "x".to_string() },
+                           &Shape::Text { x: label + 6, y: 65, text: "ab".to_string() }]);
+
+    let mut svg = vec![];
+    render::svg(&mut svg, &image).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.contains(">ab</text>"));
+
+    let pixels = render::raster(&image);
+    assert_eq!(pixels.len(), image.width * image.height);
+    assert_eq!(pixels[55 * image.width + label], 0);
+    assert_eq!(pixels[55 * image.width + label + 17], 255);
+    println!("render layout OK");
+}
+
+fn test_png() {
+    let raw: Vec<u8> = (0..100).map(|i| (i / 10) as u8).collect();
+    let store = mipmap::mipmap(&raw);
+    let names = vec!["d0".to_string()];
+    let c = Config { from: 0, to: 100, width: 50, samplerate: 0 };
+    let image = render::layout(&store, &names, &[], &c);
+    let mut png = vec![];
+    render::png(&mut png, &image).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..20], &(image.width as u32).to_be_bytes());
+    assert_eq!(&png[png.len()-8..png.len()-4], b"IEND");
+    let empty = render::Image { width: 0, height: 20, shapes: vec![] };
+    assert!(render::png(&mut vec![], &empty).is_err());
+    println!("render png OK");
+}
+
+fn main() {
+    test_ticks();
+    test_layout();
+    test_png();
+}
+
+#[test]
+fn run_tests() { main() }
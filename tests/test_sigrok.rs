@@ -0,0 +1,76 @@
+extern crate logan;
+extern crate zip;
+use logan::sigrok;
+use std::io::{Cursor,Write};
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+fn test_samplerate() {
+    assert_eq!(sigrok::parse_samplerate("1 MHz"), Some(1000000));
+    assert_eq!(sigrok::parse_samplerate("500 kHz"), Some(500000));
+    assert_eq!(sigrok::parse_samplerate("1.5 MHz"), Some(1500000));
+    assert_eq!(sigrok::parse_samplerate("200"), Some(200));
+    assert_eq!(sigrok::parse_samplerate("fast"), None);
+    assert_eq!(sigrok::format_samplerate(24000000), "24 MHz");
+    assert_eq!(sigrok::format_samplerate(1500000), "1500 kHz");
+    assert_eq!(sigrok::format_samplerate(300), "300 Hz");
+    println!("sigrok samplerate OK");
+}
+
+fn roundtrip(session: &sigrok::Session) -> sigrok::Session {
+    let mut buf = Cursor::new(vec![]);
+    sigrok::write_to(&mut buf, session).unwrap();
+    buf.set_position(0);
+    sigrok::read_from(buf).unwrap()
+}
+
+fn test_roundtrip() {
+    for &nb_probes in [3, 8, 12].iter() {
+        let names: Vec<String> = (0..nb_probes).map(|i| format!("p{}", i)).collect();
+        let mut s = sigrok::Session::new(4000000, names.clone()).unwrap();
+        let mask = (1 << nb_probes) - 1;
+        let bus: Vec<usize> = (0..10000).map(|i| (i * 7919) & mask).collect();
+        for &b in bus.iter() { s.push(b); }
+        assert_eq!(s.len(), bus.len());
+        let r = roundtrip(&s);
+        assert_eq!(r.samplerate, 4000000);
+        assert_eq!(r.probes, names);
+        assert_eq!(r.unitsize, if nb_probes > 8 { 2 } else { 1 });
+        assert_eq!(r.samples().collect::<Vec<_>>(), bus);
+    }
+    println!("sigrok roundtrip OK");
+}
+
+// Session file with the given device metadata and one sample.
+fn archive(device: &str) -> Cursor<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    zip.start_file("version", FileOptions::default()).unwrap();
+    zip.write_all(b"2").unwrap();
+    zip.start_file("metadata", FileOptions::default()).unwrap();
+    write!(zip, "[device 1]\ncapturefile=logic-1\nsamplerate=1 MHz\n{}", device).unwrap();
+    zip.start_file("logic-1", FileOptions::default()).unwrap();
+    zip.write_all(&[0]).unwrap();
+    let mut buf = zip.finish().unwrap();
+    buf.set_position(0);
+    buf
+}
+
+fn test_probes() {
+    let names: Vec<String> = (0..65).map(|i| format!("p{}", i)).collect();
+    assert!(sigrok::Session::new(1000, names).is_err());
+    // Probe numbers start at 1.
+    assert!(sigrok::read_from(archive("probe1=a\nunitsize=1\n")).is_ok());
+    assert!(sigrok::read_from(archive("probe0=a\nunitsize=1\n")).is_err());
+    println!("sigrok probes OK");
+}
+
+fn main() {
+    test_samplerate();
+    test_roundtrip();
+    test_probes();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,112 @@
+extern crate logan;
+use logan::io::{Sink,Flush};
+use std::io::{self,Write};
+use std::sync::{Arc,Mutex};
+use std::time::Duration;
+use std::thread;
+
+// Output that can be inspected while the sink holds it.
+#[derive(Clone)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+impl Write for Shared {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(data);
+        Ok(data.len())
+    }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+fn shared() -> (Shared, Arc<Mutex<Vec<u8>>>) {
+    let v = Arc::new(Mutex::new(vec![]));
+    (Shared(v.clone()), v)
+}
+fn len(v: &Arc<Mutex<Vec<u8>>>) -> usize {
+    v.lock().unwrap().len()
+}
+
+fn test_policies() {
+    assert_eq!(Flush::parse("bytes:10"), Some(Flush::Bytes(10)));
+    assert_eq!(Flush::parse("idle:5"), Some(Flush::Idle(Duration::from_millis(5))));
+    assert_eq!(Flush::parse("line"), Some(Flush::Line));
+    assert_eq!(Flush::parse("often"), None);
+
+    let (out, v) = shared();
+    let mut s = Sink::new(out, Flush::Item);
+    s.write_all(b"ab").unwrap();
+    assert_eq!(len(&v), 0);
+    s.flush().unwrap();
+    assert_eq!(len(&v), 2);
+
+    let (out, v) = shared();
+    let mut s = Sink::new(out, Flush::Line);
+    s.write_all(b"ab").unwrap();
+    s.flush().unwrap();
+    assert_eq!(len(&v), 0);
+    s.write_all(b"c\n").unwrap();
+    s.flush().unwrap();
+    assert_eq!(len(&v), 4);
+
+    let (out, v) = shared();
+    let mut s = Sink::new(out, Flush::Bytes(4));
+    s.write_all(b"abc").unwrap();
+    s.flush().unwrap();
+    assert_eq!(len(&v), 0);
+    s.write_all(b"d").unwrap();
+    assert_eq!(len(&v), 4);
+    s.write_all(b"e").unwrap();
+    drop(s);
+    assert_eq!(*v.lock().unwrap(), b"abcde".to_vec());
+    println!("sink policies OK");
+}
+
+fn test_idle() {
+    let (out, v) = shared();
+    let mut s = Sink::idle(out, Duration::from_millis(10));
+    s.write_all(b"abc").unwrap();
+    s.flush().unwrap();
+    assert_eq!(len(&v), 0);
+    for _ in 0..100 {
+        if len(&v) == 3 { break; }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(len(&v), 3);
+
+    // Without the timer, the next write after the timeout writes out.
+    let (out, v) = shared();
+    let mut s = Sink::new(out, Flush::Idle(Duration::from_millis(10)));
+    s.write_all(b"abc").unwrap();
+    thread::sleep(Duration::from_millis(20));
+    assert_eq!(len(&v), 0);
+    s.write_all(b"d").unwrap();
+    assert_eq!(len(&v), 3);
+    println!("sink idle OK");
+}
+
+// Output that fails every write.
+struct Broken;
+impl Write for Broken {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+    }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+// An error in the idle flusher is returned by the next call.
+fn test_error() {
+    let mut s = Sink::idle(Broken, Duration::from_millis(5));
+    s.write_all(b"abc").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(s.flush().unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    s.flush().unwrap();
+    println!("sink error OK");
+}
+
+fn main() {
+    test_policies();
+    test_idle();
+    test_error();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
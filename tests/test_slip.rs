@@ -1,6 +1,7 @@
-extern crate la;
-use la::sm::apply;
-use la::sm::slip;
+extern crate logan;
+use logan::sm::apply;
+use logan::sm::slip;
+use logan::sm::Push;
 
 
 fn test1() {
@@ -26,8 +27,30 @@ fn test1() {
     println!("slip OK");
 }
 
+// Unterminated packet is reported at end of input, and reset drops
+// accumulated data.
+fn test_flush() {
+    let mut slip = slip::init(
+        slip::Config{
+            end: 0x0D,
+            esc: 0x0C,
+            esc_end: 0x0B,
+            esc_esc: 0x0A,
+        }
+    );
+    let test_data = [1,2,0x0D,3,0x0C,0x0B];
+    let data_out: Vec<_> = apply(&mut slip, test_data.iter()).collect();
+    assert_eq!(data_out, [[1,2].to_vec(), [3,0x0D].to_vec()].to_vec());
+
+    for b in [4,5].iter() { slip.push(b); }
+    slip.reset();
+    assert_eq!(slip.flush(), None);
+    println!("slip flush OK");
+}
+
 fn main() {
     test1();
+    test_flush();
 }
 
 #[test]
@@ -1,6 +1,8 @@
-extern crate la;
-use la::sm::apply;
-use la::sm::syncser;
+extern crate logan;
+use logan::sm::apply;
+use logan::sm::syncser;
+use logan::sm::Push;
+use logan::gen::{self,Encode};
 
 /* Currently returning a sequence with closures is not possible
 without workarounds, so use a macro.  What would help is Box<Fn>
@@ -46,8 +48,15 @@ fn test_vec(syncser: &mut syncser::SyncSer, data_in: Vec<usize>, period: usize)
               test_seq!(c, data_in, period)
         ).collect();
     assert_eq!(data_out, data_in);
+    // The generator clocks data on the other phase, so check it
+    // through the decoder.
+    syncser.reset();
+    let bus = gen::spi(c, period).encode(&data_in);
+    let data_out: Vec<_> = apply(syncser, bus.into_iter()).collect();
+    assert_eq!(data_out, data_in);
 }
 
+#[allow(clippy::redundant_field_names)]
 fn test_configs() {
     let nb_bits = 8;
     for edge in 0..2 {
@@ -75,8 +84,28 @@ fn test_configs() {
     println!("syncser OK");
 }
 
+// Partial word is reported at end of input if its frame is still
+// active.
+fn test_flush() {
+    let mut c = syncser::config();
+    c.frame_channel = 2;
+    c.frame_enable = true;
+    let mut syncser = syncser::init(c);
+    // 3 bits 1,0,1 clocked in with frame active (0)
+    let bus = [0b000usize, 0b011, 0b010, 0b001, 0b000, 0b011, 0b010];
+    let data_out: Vec<_> = apply(&mut syncser, bus.iter()).collect();
+    assert_eq!(data_out, vec![0b101]);
+    // Ended frame drops the partial word.
+    syncser.reset();
+    for b in bus.iter() { syncser.push(b); }
+    syncser.push(0b100);
+    assert_eq!(syncser.flush(), None);
+    println!("syncser flush OK");
+}
+
 fn main() {
     test_configs();
+    test_flush();
 }
 
 #[test]
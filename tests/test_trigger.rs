@@ -0,0 +1,119 @@
+extern crate logan;
+use logan::sm::{apply,uart};
+use logan::decoder::{Registry,Options};
+use logan::trigger;
+use logan::gen::{self,Encode};
+
+fn options(args: &[&str]) -> Options {
+    Options::parse(args.iter().map(|a| a.to_string())).unwrap()
+}
+
+fn fire(spec: &str, opts: &Options, bus: &[usize]) -> Vec<usize> {
+    let registry = Registry::builtin();
+    let mut t = trigger::parse(spec, &registry, opts).unwrap();
+    apply(&mut t, bus.iter()).collect()
+}
+
+// UART frames at 1 bit per sample, with an idle bit in between.
+fn uart(bytes: &[u8]) -> Vec<usize> {
+    let mut g = gen::uart(uart::Config { period: 1, nb_bits: 8, channel: 0 });
+    g.stop_bits = 2;
+    g.idle = 0;
+    let mut bus = vec![1, 1];
+    bus.extend(g.encode(&bytes.iter().map(|&b| b as usize).collect::<Vec<_>>()));
+    bus
+}
+
+fn test_pattern() {
+    let none = options(&[]);
+    let bus = [0b00, 0b01, 0b11, 0b10, 0b11, 0b01, 0b00];
+    assert_eq!(fire("11", &none, &bus), vec![2, 4]);
+    assert_eq!(fire("x1", &none, &bus), vec![2, 3, 4]);
+    assert_eq!(fire("R", &none, &bus), vec![1, 4]);
+    assert_eq!(fire("FX", &none, &bus), vec![3, 6]);
+    assert_eq!(fire("1R", &none, &bus), vec![2]);
+    // No edges on the first sample.
+    assert_eq!(fire("0", &none, &bus), vec![0, 3, 6]);
+    // One character per bus bit at most.
+    assert!(trigger::parse(&"x".repeat(64), &Registry::builtin(), &none).is_ok());
+    assert!(trigger::parse(&"x".repeat(65), &Registry::builtin(), &none).is_err());
+    println!("trigger pattern OK");
+}
+
+fn test_pulse() {
+    let none = options(&[]);
+    let bus = [0, 1, 0, 0, 1, 1, 1, 0, 0, 0, 0, 1, 0];
+    assert_eq!(fire("w:0:h:1:1", &none, &bus), vec![2, 12]);
+    assert_eq!(fire("w:0:h:2", &none, &bus), vec![7]);
+    // The first low pulse starts before the capture.
+    assert_eq!(fire("w:0:l:2:4", &none, &bus), vec![4, 11]);
+    assert!(trigger::parse("w:0:x:1", &Registry::builtin(), &none).is_err());
+    assert!(trigger::parse("w:64:h:1", &Registry::builtin(), &none).is_err());
+    println!("trigger pulse OK");
+}
+
+fn test_sequence() {
+    let none = options(&[]);
+    let bus = [0, 1, 0, 1, 1, 0, 1, 0];
+    // A falling edge, then a high level for 2 samples.
+    assert_eq!(fire("F/w:0:h:2:2", &none, &bus), vec![5]);
+    // Stages advance on separate samples.
+    assert_eq!(fire("R/1", &none, &bus), vec![3]);
+    assert_eq!(fire("R/R", &none, &bus), vec![3]);
+    println!("trigger sequence OK");
+}
+
+fn test_decoder() {
+    let opts = options(&["samplerate=1", "baudrate=1"]);
+    let bus = uart(b"a~b~");
+    let t = fire("uart:0x7e", &opts, &bus);
+    assert_eq!(t.len(), 2);
+    assert_eq!(fire("uart", &opts, &bus).len(), 4);
+    // The decoder sees the samples before the first stage matched.
+    assert_eq!(fire("R/uart:0x62", &opts, &bus).len(), 1);
+    println!("trigger decoder OK");
+}
+
+fn test_gate() {
+    let registry = Registry::builtin();
+    let none = options(&[]);
+    let mut bus = [0; 20];
+    bus[5] = 1;
+    bus[15] = 1;
+    let gated = |config: trigger::Config| {
+        let t = trigger::parse("R", &registry, &none).unwrap();
+        let mut g = trigger::gate(t, config);
+        let out: Vec<(usize, usize)> = apply(&mut g, bus.iter()).collect();
+        out
+    };
+    let out = gated(trigger::Config { pre: 2, post: 1, count: 0 });
+    let t: Vec<usize> = out.iter().map(|&(t, _)| t).collect();
+    assert_eq!(t, vec![3, 4, 5, 6, 13, 14, 15, 16]);
+    assert_eq!(out[2], (5, 1));
+    let out = gated(trigger::Config { pre: 10, post: 0, count: 1 });
+    assert_eq!(out.len(), 6);
+    assert_eq!(out[0], (0, 0));
+    // Re-armed only after the post-trigger samples.
+    let out = gated(trigger::Config { pre: 0, post: 12, count: 0 });
+    assert_eq!(out.len(), 13);
+    // With a count, input is read only up to the end of the last window.
+    let t = trigger::parse("R", &registry, &none).unwrap();
+    let mut g = trigger::gate(t, trigger::Config { pre: 2, post: 1, count: 1 });
+    let mut input = bus.iter();
+    let out: Vec<(usize, usize)> = trigger::apply(&mut g, input.by_ref()).collect();
+    assert_eq!(out.len(), 4);
+    assert!(g.done());
+    assert_eq!(input.len(), 20 - 9);
+    println!("trigger gate OK");
+}
+
+fn main() {
+    test_pattern();
+    test_pulse();
+    test_sequence();
+    test_decoder();
+    test_gate();
+}
+
+#[test]
+fn run_tests() { main() }
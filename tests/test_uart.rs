@@ -1,5 +1,6 @@
-extern crate la;
-use la::sm::{apply,uart};
+extern crate logan;
+use logan::sm::{apply,uart};
+use logan::gen::{self,Encode};
 
 fn frame(nb_bits: usize, value: usize) -> usize {
     (value | (1 << nb_bits)) << 1
@@ -7,7 +8,7 @@ fn frame(nb_bits: usize, value: usize) -> usize {
 fn test_vec(uart: &mut uart::Uart, data_in: Vec<usize>) {
     let c = uart.config;
 
-    let mut test_data =
+    let test_data: Vec<usize> =
         data_in.iter()
         // expand data word to UART frame bit sequence
         .flat_map(
@@ -24,17 +25,22 @@ fn test_vec(uart: &mut uart::Uart, data_in: Vec<usize>) {
             |bus|
             (0..c.period).map(
                 move |_|
-                bus));
+                bus))
+        .collect();
 
     // decode it
     let data_out: Vec<_> =
-        apply(uart, &mut test_data).collect();
+        apply(uart, test_data.iter()).collect();
 
     assert_eq!(data_out, data_in);
-}
-
 
+    // The generator has to produce the same frames, back to back.
+    let mut g = gen::uart(c);
+    g.idle = 0;
+    assert_eq!(g.encode(&data_in), test_data);
+}
 
+#[allow(clippy::redundant_field_names)]
 fn test_configs() {
     for period in 1..20 {
         println!("{} {}", period, uart::start_delay(period));
@@ -59,8 +65,23 @@ fn test_configs() {
     println!("uart OK");
 }
 
+// Capture ending before the stop bit still reports the word.
+fn test_flush() {
+    let c = uart::Config { period: 4, nb_bits: 8, channel: 0 };
+    let mut uart = uart::init(c);
+    let bus: Vec<usize> = (0..9)
+        .map(|shift| (frame(8, 0x5A) >> shift) & 1)
+        .flat_map(|bit| (0..c.period).map(move |_| bit))
+        .collect();
+    let data_out: Vec<_> = apply(&mut uart, bus.iter()).collect();
+    assert_eq!(data_out, vec![0x5A]);
+    assert_eq!(uart.flush(), None);
+    println!("uart flush OK");
+}
+
 fn main() {
     test_configs();
+    test_flush();
 }
 
 #[test]
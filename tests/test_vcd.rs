@@ -0,0 +1,139 @@
+extern crate logan;
+use logan::vcd;
+use logan::sm::{apply,uart};
+
+fn test_timescale() {
+    assert_eq!(vcd::timescale(1), (1, "s"));
+    assert_eq!(vcd::timescale(1000), (1, "ms"));
+    assert_eq!(vcd::timescale(2000000), (500, "ns"));
+    assert_eq!(vcd::timescale(3), (333333333333333, "fs"));
+    println!("vcd timescale OK");
+}
+
+fn test_writer() {
+    let names = vec!["clk".to_string(), "data out".to_string()];
+    let mut out = vec![];
+    {
+        let mut w = vcd::Writer::new(&mut out, 1000000, &names);
+        let s = w.add("uart", vcd::Kind::String);
+        for (t, &bus) in [0b00usize, 0b01, 0b01, 0b11].iter().enumerate() {
+            w.sample(t as u64, bus).unwrap();
+        }
+        w.string(3, s, "a b").unwrap();
+        w.finish(4).unwrap();
+    }
+    let text = String::from_utf8(out).unwrap();
+    let expected = "\
+$version logan $end
+$timescale 1 us $end
+$scope module logan $end
+$var wire 1 ! clk $end
+$var wire 1 \" data_out $end
+$var string 1 # uart $end
+$upscope $end
+$enddefinitions $end
+#0
+0!
+0\"
+#1
+1!
+#3
+1\"
+sa\\x20b #
+#4
+";
+    assert_eq!(text, expected);
+    println!("vcd writer OK");
+}
+
+
+const DUMP: &str = "\
+$date today $end
+$timescale 1ns $end
+$scope module top $end
+$var wire 1 ! clk $end
+$var wire 2 # data [1:0] $end
+$scope module sub $end
+$var reg 1 % clk $end
+$upscope $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+b0 #
+x%
+$end
+#1000
+1!
+b10 #
+#2500
+0!
+1%
+#4000
+";
+
+fn test_resample() {
+    // 1 MHz: samples at 0, 1000, 2000, 3000, 4000 ns
+    let r = vcd::Reader::new(DUMP.as_bytes(), &[], 1000000).unwrap();
+    assert_eq!(r.names(), ["top.clk", "top.data", "top.sub.clk"]);
+    let bus: Vec<usize> = r.collect();
+    assert_eq!(bus, vec![0b0000, 0b0101, 0b0101, 0b1100, 0b1100]);
+
+    // Selection by name and full name
+    let wires = vec!["top.sub.clk".to_string(), "clk".to_string()];
+    let r = vcd::Reader::new(DUMP.as_bytes(), &wires, 2000000).unwrap();
+    let bus: Vec<usize> = r.collect();
+    assert_eq!(bus, vec![0, 0, 2, 2, 2, 1, 1, 1, 1]);
+
+    assert!(vcd::Reader::new(DUMP.as_bytes(), &["nope".to_string()], 1).is_err());
+
+    // A bad token ends the samples early, with the error kept.
+    let bad = DUMP.replace("#4000", "#40x0");
+    let mut r = vcd::Reader::new(bad.as_bytes(), &[], 1000000).unwrap();
+    assert!(r.error().is_none());
+    assert_eq!(r.by_ref().count(), 3);
+    assert!(r.error().is_some());
+    println!("vcd resample OK");
+}
+
+// Writer output read back and decoded.
+fn test_roundtrip() {
+    let c = uart::Config { period: 4, nb_bits: 8, channel: 1 };
+    let data_in: Vec<usize> = vec![0x55, 0x00, 0xff, 0x12];
+    let mut bus = vec![2usize; 8];
+    for &data in data_in.iter() {
+        for shift in 0..c.nb_bits+2 {
+            let bit = (((data | (1 << c.nb_bits)) << 1) >> shift) & 1;
+            bus.extend((0..c.period).map(|_| bit << 1));
+        }
+    }
+    let names = vec!["a".to_string(), "tx".to_string()];
+    let mut out = vec![];
+    {
+        let mut w = vcd::Writer::new(&mut out, 3000000, &names);
+        for (t, &b) in bus.iter().enumerate() {
+            w.sample(t as u64, b).unwrap();
+        }
+        w.finish(bus.len() as u64).unwrap();
+    }
+    let r = vcd::Reader::new(&out[..], &[], 3000000).unwrap();
+    let read: Vec<usize> = r.collect();
+    assert_eq!(&read[..bus.len()], &bus[..]);
+    let mut u = uart::init(c);
+    let data_out: Vec<usize> = apply(&mut u, read.into_iter()).collect();
+    assert_eq!(data_out, data_in);
+    println!("vcd roundtrip OK");
+}
+
+fn main() {
+    test_timescale();
+    test_writer();
+    test_resample();
+    test_roundtrip();
+}
+
+#[test]
+fn run_tests() {
+    main()
+}
@@ -0,0 +1,73 @@
+extern crate logan;
+use logan::mipmap;
+use logan::view::{self,Window,Annotation,Key};
+use logan::decoder::Item;
+
+fn names(n: usize) -> Vec<String> {
+    (0..n).map(|c| format!("d{}", c)).collect()
+}
+
+fn test_render() {
+    // d0 toggles every sample, d1 is high in the second half.
+    let raw: Vec<u8> = (0..16).map(|i| (i & 1) | if i >= 8 { 2 } else { 0 }).collect();
+    let store = mipmap::mipmap(&raw);
+    let w = Window { start: 0, zoom: 2, width: 5, samplerate: 0 };
+    let lines = view::render(&store, &names(2), &[], &w);
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "   0..20, 4 samples/col");
+    assert_eq!(lines[1], "d0 \u{2592}\u{2592}\u{2592}\u{2592} ");
+    assert_eq!(lines[2], "d1 __\u{203e}\u{203e} ");
+
+    let w = Window { start: 8, zoom: 0, width: 2, samplerate: 0 };
+    let lines = view::render(&store, &names(2), &[], &w);
+    assert_eq!(lines[1], "d0 _\u{203e}");
+    assert_eq!(lines[2], "d1 \u{203e}\u{203e}");
+    println!("view render OK");
+}
+
+fn test_annotation() {
+    let raw = vec![0u8; 32];
+    let store = mipmap::mipmap(&raw);
+    let a = Annotation {
+        name: "uart".to_string(),
+        items: vec![(0, "A".to_string()), (1, "B".to_string()),
+                    (8, "cd".to_string()), (30, "xyz".to_string())],
+    };
+    let w = Window { start: 0, zoom: 1, width: 16, samplerate: 0 };
+    let lines = view::render(&store, &names(1), &[a], &w);
+    // Overlapping items are dropped, text is clipped at the edge.
+    assert_eq!(lines[2], "uart A   cd         x");
+    assert_eq!(view::label(&Item::Byte(b'A')), "A");
+    assert_eq!(view::label(&Item::Byte(b' ')), "20");
+    assert_eq!(view::label(&Item::Word(0x1ff)), "1ff");
+    assert_eq!(view::label(&Item::Packet(vec![1, 0xab])), "01ab");
+    println!("view annotation OK");
+}
+
+fn test_window() {
+    assert_eq!(view::fit(100, 10), 4);
+    assert_eq!(view::fit(160, 10), 4);
+    assert_eq!(view::fit(5, 10), 0);
+    let mut w = Window { start: 0, zoom: 4, width: 10, samplerate: 0 };
+    w.apply(&view::key(b"l"), 160, 4);
+    assert_eq!(w.start, 40);
+    w.apply(&view::key(b"+"), 160, 4);
+    assert_eq!((w.start, w.zoom), (80, 3));
+    w.apply(&view::key(b"\x1b[B"), 160, 4);
+    assert_eq!((w.start, w.zoom), (40, 4));
+    w.apply(&view::key(b"-"), 160, 4);
+    assert_eq!(w.zoom, 4);
+    w.apply(&view::key(b"G"), 160, 4);
+    assert_eq!(w.start, 0);
+    assert!(matches!(view::key(b"q"), Key::Quit));
+    println!("view window OK");
+}
+
+fn main() {
+    test_render();
+    test_annotation();
+    test_window();
+}
+
+#[test]
+fn run_tests() { main() }